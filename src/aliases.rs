@@ -0,0 +1,322 @@
+use log::info;
+use poise::serenity_prelude::{Context as SerenityContext, MessageBuilder, Role, RoleId};
+use serde_derive::{Deserialize, Serialize};
+use tqdb::{remove, search};
+
+use crate::api::{self, ApiError, GuildId, Table};
+use crate::util::*;
+use crate::{Context, Data, Error};
+
+/// Another name a role can be pinged or created by, always stored in lowercase.
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+pub struct RoleAlias {
+    guild_id: GuildId,
+    role_id: api::RoleId,
+    alias: String,
+}
+
+impl Table<RoleAlias> {
+    pub fn add_alias<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &mut self,
+        guild_id: G,
+        role_id: R,
+        alias: &str,
+    ) -> Result<(), ApiError> {
+        let guild_id = guild_id.into();
+        let alias = normalize(alias);
+        if search!(&self.0 => |it: &RoleAlias| it.guild_id == guild_id && it.alias == alias)
+            .next()
+            .is_some()
+        {
+            return Err(ApiError::Insertion);
+        }
+        self.0
+            .insert_unique(RoleAlias {
+                guild_id,
+                role_id: role_id.into(),
+                alias,
+            })
+            .map_err(|_| ApiError::Insertion)
+    }
+
+    /// Remove an alias of a role, failing when it belongs to another role.
+    pub fn remove_alias<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &mut self,
+        guild_id: G,
+        role_id: R,
+        alias: &str,
+    ) -> Result<(), ApiError> {
+        let guild_id = guild_id.into();
+        let role_id = role_id.into();
+        let alias = normalize(alias);
+        remove!(&mut self.0 => move |it: &RoleAlias| it.guild_id == guild_id && it.role_id == role_id && it.alias == alias)
+            .next()
+            .map(|_| ())
+            .ok_or(ApiError::Removal)
+    }
+
+    pub fn show_aliases_of_role<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &self,
+        guild_id: G,
+        role_id: R,
+    ) -> Vec<&String> {
+        let guild_id = guild_id.into();
+        let role_id = role_id.into();
+        search!(&self.0 => move |it: &RoleAlias| it.guild_id == guild_id && it.role_id == role_id)
+            .map(|it| &it.alias)
+            .collect()
+    }
+
+    pub fn role_of_alias<G: Into<GuildId>>(&self, guild_id: G, alias: &str) -> Option<api::RoleId> {
+        let guild_id = guild_id.into();
+        let alias = normalize(alias);
+        search!(&self.0 => move |it: &RoleAlias| it.guild_id == guild_id && it.alias == alias)
+            .next()
+            .map(|it| it.role_id)
+    }
+
+    pub fn show_aliases_of_guild<G: Into<GuildId>>(
+        &self,
+        guild_id: G,
+    ) -> Vec<(api::RoleId, String)> {
+        let guild_id = guild_id.into();
+        search!(&self.0 => move |it: &RoleAlias| it.guild_id == guild_id)
+            .map(|it| (it.role_id, it.alias.clone()))
+            .collect()
+    }
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Roles whose name or alias appears as whole words in the text.
+pub fn match_names(text: &str, names: &[(api::RoleId, String)]) -> Vec<api::RoleId> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+
+    let mut res: Vec<api::RoleId> = names
+        .iter()
+        .filter(|(_, name)| {
+            let name: Vec<String> = name
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|w| !w.is_empty())
+                .map(str::to_lowercase)
+                .collect();
+            !name.is_empty() && words.windows(name.len()).any(|w| w == name.as_slice())
+        })
+        .map(|(id, _)| *id)
+        .collect();
+    res.sort();
+    res.dedup();
+    res
+}
+
+/// Names and aliases of every tracked role in a guild.
+///
+/// Aliases of roles that were deleted are left out.
+pub fn names_of_guild(
+    ctx: &SerenityContext,
+    user_data: &Data,
+    guild_id: GuildId,
+) -> Vec<(api::RoleId, String)> {
+    let tracked: Vec<api::RoleId> = user_data
+        .roles
+        .lock()
+        .unwrap()
+        .show_roles_of_guild(guild_id)
+        .into_iter()
+        .copied()
+        .collect();
+    let existing: Vec<(api::RoleId, String)> = tracked
+        .into_iter()
+        .filter_map(|id| {
+            RoleId::from(id)
+                .to_role_cached(ctx)
+                .map(|role| (id, role.name))
+        })
+        .collect();
+    let mut names: Vec<(api::RoleId, String)> = user_data
+        .aliases
+        .lock()
+        .unwrap()
+        .show_aliases_of_guild(guild_id)
+        .into_iter()
+        .filter(|(id, _)| existing.iter().any(|(it, _)| it == id))
+        .collect();
+    names.extend(existing);
+    names
+}
+
+/// The text after the trigger, when the content starts with it as a whole word.
+pub fn strip_trigger<'a>(content: &'a str, trigger: &str) -> Option<&'a str> {
+    let text = content.trim_start().strip_prefix(trigger)?;
+    match text.chars().next() {
+        None => Some(text),
+        Some(c) if c.is_whitespace() => Some(text),
+        Some(_) => None,
+    }
+}
+
+/// Roles triggered by plain text, when the guild has configured a trigger prefix.
+pub fn triggered_roles(
+    ctx: &SerenityContext,
+    user_data: &Data,
+    guild_id: GuildId,
+    content: &str,
+) -> Vec<RoleId> {
+    let trigger = match user_data
+        .settings
+        .lock()
+        .unwrap()
+        .settings_of_guild(guild_id)
+        .trigger
+    {
+        Some(trigger) => trigger,
+        None => return Vec::new(),
+    };
+
+    let text = match strip_trigger(content, &trigger) {
+        Some(text) => text,
+        None => return Vec::new(),
+    };
+
+    let names = names_of_guild(ctx, user_data, guild_id);
+    match_names(text, &names)
+        .into_iter()
+        .map(RoleId::from)
+        .collect()
+}
+
+#[derive(poise::SlashChoiceParameter)]
+pub enum AliasAction {
+    #[name = "Add the alias"]
+    Add,
+    #[name = "Remove the alias"]
+    Remove,
+}
+
+/// Show or change the other names of a role
+#[poise::command(
+    slash_command,
+    category = "game",
+    required_permissions = "MANAGE_ROLES"
+)]
+pub async fn alias(
+    ctx: Context<'_>,
+    #[description = "Selected role"] role: Role,
+    #[description = "Change to make"] action: Option<AliasAction>,
+    #[description = "Alias to add or remove"] alias: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    if let (Some(action), Some(alias)) = (action, &alias) {
+        let choice = match action {
+            AliasAction::Add => ctx
+                .data()
+                .aliases
+                .lock()
+                .unwrap()
+                .add_alias(guild_id, role.id, alias),
+            AliasAction::Remove => ctx
+                .data()
+                .aliases
+                .lock()
+                .unwrap()
+                .remove_alias(guild_id, role.id, alias),
+        };
+
+        if choice.is_err() {
+            let message = MessageBuilder::new()
+                .push("Failed to change the alias ")
+                .push_mono_safe(alias)
+                .push("!")
+                .build();
+            ctx.send(|f| f.embed(unsuccessful_interaction(|f| f.description(message))))
+                .await?;
+            return Ok(());
+        }
+
+        info!(
+            "({}) {} changed the aliases of {}!",
+            guild_id,
+            ctx.author().id,
+            role.id
+        );
+        save_table("aliases", &ctx.data().aliases.lock().unwrap());
+    }
+
+    let aliases: Vec<String> = ctx
+        .data()
+        .aliases
+        .lock()
+        .unwrap()
+        .show_aliases_of_role(guild_id, role.id)
+        .into_iter()
+        .cloned()
+        .collect();
+
+    let mut message = MessageBuilder::new();
+    message.push("Aliases of ").role(&role).push_line(":");
+    if aliases.is_empty() {
+        message.push_italic("None");
+    }
+    for alias in aliases {
+        message.push("• ").push_mono_line_safe(alias);
+    }
+    let message = message.build();
+
+    ctx.send(|f| f.embed(successful_interaction(|f| f.description(message))))
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aliases::*;
+
+    #[test]
+    pub fn test_match_names() {
+        let names = vec![
+            (1, "dota".to_string()),
+            (1, "d2".to_string()),
+            (2, "Apex Legends".to_string()),
+            (3, "cs".to_string()),
+        ];
+        assert_eq!(match_names(" Dota anyone?", &names), vec![1]);
+        assert_eq!(match_names(" d2 or apex legends", &names), vec![1, 2]);
+        assert!(match_names(" apex", &names).is_empty());
+        assert!(match_names(" csgo", &names).is_empty());
+    }
+
+    #[test]
+    pub fn test_strip_trigger() {
+        assert_eq!(strip_trigger("  !lfg dota", "!lfg"), Some(" dota"));
+        assert_eq!(strip_trigger("!lfg", "!lfg"), Some(""));
+        assert_eq!(strip_trigger("!lfgdota", "!lfg"), None);
+        assert_eq!(strip_trigger("dota !lfg", "!lfg"), None);
+    }
+
+    #[test]
+    pub fn test_remove_alias() {
+        let mut aliases = Table::default();
+        aliases.add_alias(1, 2, "Dota").unwrap();
+        assert!(matches!(
+            aliases.remove_alias(1, 3, "dota"),
+            Err(ApiError::Removal)
+        ));
+        assert_eq!(aliases.role_of_alias(1, "dota"), Some(2));
+        assert!(aliases.remove_alias(1, 2, "DOTA").is_ok());
+        assert_eq!(aliases.role_of_alias(1, "dota"), None);
+    }
+}
@@ -3,6 +3,8 @@
 
 #[cfg(test)]
 use mutagen::mutate;
+use serde::de::DeserializeOwned;
+use serde::Serialize as SerializeTrait;
 use serde_derive::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::BufReader;
@@ -104,6 +106,33 @@ impl RolesDatabase {
         res
     }
 
+    /// Users subscribed to any of the roles, each user only appearing once.
+    #[cfg_attr(test, mutate)]
+    pub fn show_users_of_roles<G: Into<GuildId>>(
+        &self,
+        guild_id: G,
+        role_ids: &[RoleId],
+    ) -> Vec<&UserId> {
+        let guild_id = guild_id.into();
+        let mut res: Vec<_> = search!(&self.0 => move |it: &Roles| it.guild_id == guild_id && role_ids.contains(&it.role_id))
+            .map(|it| &it.user_id)
+            .collect();
+        res.sort();
+        res.dedup();
+        res
+    }
+
+    /// Every role with subscribers, paired with its guild.
+    #[cfg_attr(test, mutate)]
+    pub fn show_tracked_roles(&self) -> Vec<(GuildId, RoleId)> {
+        let mut res = search!(&self.0 => |_: &Roles| true)
+            .map(|it| (it.guild_id, it.role_id))
+            .collect::<Vec<_>>();
+        res.sort();
+        res.dedup();
+        res
+    }
+
     #[cfg_attr(test, mutate)]
     pub fn show_roles_of_guild<G: Into<GuildId>>(&self, guild_id: G) -> Vec<&RoleId> {
         let guild_id = guild_id.into();
@@ -149,6 +178,28 @@ impl RolesDatabase {
         }
     }
 
+    /// Move the subscribers of a role to another, returning how many moved and were already there.
+    #[cfg_attr(test, mutate)]
+    pub fn merge_roles<G: Into<GuildId>, R: Into<RoleId>>(
+        &mut self,
+        guild_id: G,
+        from: R,
+        into: R,
+    ) -> Result<(usize, usize)> {
+        let guild_id = guild_id.into();
+        let into = into.into();
+        let mut moved = 0;
+        let mut duplicates = 0;
+        for user_id in self.remove_role(guild_id, from)? {
+            match self.add_user_to_role(guild_id, into, user_id) {
+                Ok(()) => moved += 1,
+                Err(ApiError::Insertion) => duplicates += 1,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok((moved, duplicates))
+    }
+
     #[cfg_attr(test, mutate)]
     pub fn remove_user<G: Into<GuildId>, U: Into<UserId>>(
         &mut self,
@@ -182,6 +233,35 @@ impl RolesDatabase {
     pub fn save<P: AsRef<Path>>(&self, filename: P) -> Result<()> {
         self.0.save_to_file(filename).or(Err(ApiError::BadSave))
     }
+
+    pub fn row_count(&self) -> usize {
+        search!(&self.0 => |_: &Roles| true).count()
+    }
+}
+
+/// A persistent table of records, used for anything that is not a role subscription.
+#[derive(Clone, Debug, Eq, PartialEq, Default)]
+pub struct Table<T>(pub Database<T>);
+
+unsafe impl<T> Send for Table<T> {}
+unsafe impl<T> Sync for Table<T> {}
+
+impl<T: SerializeTrait + DeserializeOwned + Clone + PartialEq> Table<T> {
+    #[cfg_attr(test, mutate)]
+    pub fn try_from<'a>(filename: impl Into<&'a Path>) -> Result<Self> {
+        let file = File::open(filename.into()).map_err(|_| ApiError::BadRead)?;
+        let br = BufReader::new(file);
+        let db = Database::try_from(br).map_err(|_| ApiError::BadRead)?;
+        Ok(Self { 0: db })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, filename: P) -> Result<()> {
+        self.0.save_to_file(filename).or(Err(ApiError::BadSave))
+    }
+
+    pub fn row_count(&self) -> usize {
+        search!(&self.0 => |_: &T| true).count()
+    }
 }
 
 #[cfg(test)]
@@ -226,6 +306,17 @@ mod tests {
         assert_eq!(db.show_users_of_role(1u64, 2u64), vec![&1u64, &2u64, &6u64])
     }
 
+    #[test]
+    pub fn test_show_users_of_roles() {
+        let db = create_test_db();
+        assert_eq!(
+            db.show_users_of_roles(1u64, &[1, 2]),
+            vec![&1u64, &2u64, &3u64, &4u64, &5u64, &6u64]
+        );
+        assert_eq!(db.show_users_of_roles(2u64, &[3]), vec![&7u64]);
+        assert!(db.show_users_of_roles(1u64, &[]).is_empty());
+    }
+
     #[test]
     pub fn test_show_roles_of_guild() {
         let db = create_test_db();
@@ -233,6 +324,23 @@ mod tests {
         assert_eq!(db.show_roles_of_guild(2u64), vec![&1u64, &3u64]);
     }
 
+    #[test]
+    pub fn test_show_tracked_roles() {
+        let db = create_test_db();
+        assert_eq!(
+            db.show_tracked_roles(),
+            vec![(1, 1), (1, 2), (2, 1), (2, 3)]
+        );
+    }
+
+    #[test]
+    pub fn test_row_count() {
+        let mut db = create_test_db();
+        assert_eq!(db.row_count(), 11);
+        assert!(db.remove_guild(2u64).is_ok());
+        assert_eq!(db.row_count(), 8);
+    }
+
     #[test]
     pub fn test_remove_user_from_role() {
         let mut db = create_test_db();
@@ -248,6 +356,16 @@ mod tests {
         assert!(db.remove_role(1u64, 10000u64).is_err());
     }
 
+    #[test]
+    pub fn test_merge_roles() {
+        let mut db = create_test_db();
+
+        assert_eq!(db.merge_roles(1u64, 2u64, 1u64).unwrap(), (1, 2));
+        assert_eq!(db.show_roles_of_guild(1u64), vec![&1u64]);
+        assert_eq!(db.show_users_of_role(1u64, 1u64).len(), 6);
+        assert!(db.merge_roles(1u64, 2u64, 1u64).is_err());
+    }
+
     #[test]
     pub fn test_remove_user() {
         let mut db = create_test_db();
@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use log::{error, info};
+use poise::serenity_prelude::{
+    ButtonStyle, ChannelId, Context as SerenityContext, CreateComponents, MessageId, ReactionType,
+};
+use serde_derive::{Deserialize, Serialize};
+use tqdb::{remove, search};
+
+use crate::api::{self, ApiError, Snowflake, Table};
+use crate::components::ComponentAction;
+use crate::util::*;
+use crate::Data;
+
+/// Minutes a join button stays usable.
+pub const BUTTON_LIFETIME: i64 = 30;
+
+/// A message with a join button that has to be turned off once it expires.
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+pub struct ExpiringButton {
+    pub channel_id: Snowflake,
+    pub message_id: Snowflake,
+    pub role_id: api::RoleId,
+    /// Description of the embed the button is attached to
+    pub content: String,
+    /// Unix timestamp after which the button is turned off
+    pub expires: i64,
+}
+
+impl Table<ExpiringButton> {
+    pub fn add_button(&mut self, button: ExpiringButton) -> Result<(), ApiError> {
+        self.0
+            .insert_unique(button)
+            .map_err(|_| ApiError::Insertion)
+    }
+
+    pub fn remove_button<M: Into<Snowflake>>(
+        &mut self,
+        message_id: M,
+    ) -> Result<ExpiringButton, ApiError> {
+        let message_id = message_id.into();
+        remove!(&mut self.0 => move |it: &ExpiringButton| it.message_id == message_id)
+            .next()
+            .ok_or(ApiError::Removal)
+    }
+
+    pub fn expired_buttons(&self, now: i64) -> Vec<ExpiringButton> {
+        search!(&self.0 => move |it: &ExpiringButton| it.expires < now)
+            .cloned()
+            .collect()
+    }
+}
+
+/// A button that joins the role, or leaves it when pressed again.
+pub fn join_button(
+    role_id: api::RoleId,
+    disabled: bool,
+) -> impl FnOnce(&mut CreateComponents) -> &mut CreateComponents {
+    move |f| {
+        f.create_action_row(|f| {
+            f.create_button(|f| {
+                f.custom_id(ComponentAction::Toggle(role_id).encode())
+                    .emoji(ReactionType::from('🔔'))
+                    .style(ButtonStyle::Primary)
+                    .label("Join or leave this role!")
+                    .disabled(disabled)
+            })
+        })
+    }
+}
+
+/// Remember to turn off the join button of a message once it expires.
+pub fn schedule_expiry(
+    user_data: &Data,
+    channel_id: ChannelId,
+    message_id: MessageId,
+    role_id: api::RoleId,
+    content: String,
+) {
+    let button = ExpiringButton {
+        channel_id: channel_id.0,
+        message_id: message_id.0,
+        role_id,
+        content,
+        expires: Utc::now().timestamp() + BUTTON_LIFETIME * 60,
+    };
+    if let Err(e) = user_data.buttons.lock().unwrap().add_button(button) {
+        error!("Failed to schedule expiry of message {}! {}", message_id, e);
+    }
+    save_table("buttons", &user_data.buttons.lock().unwrap());
+}
+
+/// Turn off expired join buttons forever, including ones that expired while offline.
+pub async fn run_button_expiry(ctx: SerenityContext, user_data: Data) {
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+    loop {
+        interval.tick().await;
+        let expired = user_data
+            .buttons
+            .lock()
+            .unwrap()
+            .expired_buttons(Utc::now().timestamp());
+
+        for button in expired {
+            let _ = user_data
+                .buttons
+                .lock()
+                .unwrap()
+                .remove_button(button.message_id);
+            let edit = ChannelId::from(button.channel_id)
+                .edit_message(&ctx, MessageId::from(button.message_id), |f| {
+                    f.embed(successful_interaction(|f| {
+                        f.description(&button.content)
+                            .footer(|f| f.text("Button timed out! Do a new command."))
+                    }))
+                    .components(join_button(button.role_id, true))
+                })
+                .await;
+            // the message may have been deleted, which needs no retry
+            if let Err(e) = edit {
+                error!("Failed to turn off button of {}! {}", button.message_id, e);
+            }
+            info!("Button of {} expired!", button.message_id);
+            save_table("buttons", &user_data.buttons.lock().unwrap());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buttons::*;
+    use tqdb::Database;
+
+    fn button(message_id: Snowflake, expires: i64) -> ExpiringButton {
+        ExpiringButton {
+            channel_id: 1,
+            message_id,
+            role_id: 2,
+            content: String::new(),
+            expires,
+        }
+    }
+
+    #[test]
+    pub fn test_expired_buttons() {
+        let mut table = Table(Database::from(vec![button(1, 10), button(2, 20)]));
+        assert_eq!(table.expired_buttons(15), vec![button(1, 10)]);
+        assert!(table.remove_button(1u64).is_ok());
+        assert!(table.expired_buttons(15).is_empty());
+        assert!(table.remove_button(1u64).is_err());
+    }
+}
@@ -0,0 +1,269 @@
+use log::info;
+use poise::serenity_prelude::{MessageBuilder, Role, RoleId};
+use serde_derive::{Deserialize, Serialize};
+use tqdb::{remove, search};
+
+use crate::api::{self, ApiError, GuildId, Table};
+use crate::panels::refresh_panels;
+use crate::util::*;
+use crate::{Context, Error};
+
+/// Name of the group of roles without a category, so it can't be used for a category.
+pub const UNCATEGORIZED: &str = "Other";
+
+/// Whether a category may be called this, it must not be empty or merge with [`UNCATEGORIZED`].
+pub fn is_valid_category(name: &str) -> bool {
+    let name = name.trim();
+    !name.is_empty() && !name.eq_ignore_ascii_case(UNCATEGORIZED)
+}
+
+/// The category a role is listed under, like FPS or Board games.
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+pub struct RoleCategory {
+    guild_id: GuildId,
+    role_id: api::RoleId,
+    category: String,
+}
+
+impl Table<RoleCategory> {
+    pub fn set_category<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &mut self,
+        guild_id: G,
+        role_id: R,
+        category: &str,
+    ) {
+        let guild_id = guild_id.into();
+        let role_id = role_id.into();
+        let _ = self.clear_category(guild_id, role_id);
+        let _ = self.0.insert_unique(RoleCategory {
+            guild_id,
+            role_id,
+            category: category.trim().to_string(),
+        });
+    }
+
+    pub fn clear_category<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &mut self,
+        guild_id: G,
+        role_id: R,
+    ) -> Result<String, ApiError> {
+        let guild_id = guild_id.into();
+        let role_id = role_id.into();
+        remove!(&mut self.0 => move |it: &RoleCategory| it.guild_id == guild_id && it.role_id == role_id)
+            .next()
+            .map(|it| it.category)
+            .ok_or(ApiError::Removal)
+    }
+
+    /// Rename a category, returning how many roles are in it.
+    pub fn rename_category<G: Into<GuildId>>(
+        &mut self,
+        guild_id: G,
+        from: &str,
+        to: &str,
+    ) -> usize {
+        let guild_id = guild_id.into();
+        let renamed: Vec<RoleCategory> = remove!(&mut self.0 => move |it: &RoleCategory| it.guild_id == guild_id && it.category.eq_ignore_ascii_case(from.trim()))
+            .collect();
+        for it in renamed.iter() {
+            self.set_category(guild_id, it.role_id, to);
+        }
+        renamed.len()
+    }
+
+    pub fn show_categories_of_guild<G: Into<GuildId>>(
+        &self,
+        guild_id: G,
+    ) -> Vec<(api::RoleId, String)> {
+        let guild_id = guild_id.into();
+        search!(&self.0 => move |it: &RoleCategory| it.guild_id == guild_id)
+            .map(|it| (it.role_id, it.category.clone()))
+            .collect()
+    }
+}
+
+/// Group named roles by category, sorting categories and the roles within them by name.
+///
+/// Roles without a category come last, under [`UNCATEGORIZED`].
+pub fn group_by_category(
+    roles: Vec<(api::RoleId, String)>,
+    categories: &[(api::RoleId, String)],
+) -> Vec<(String, Vec<(api::RoleId, String)>)> {
+    let mut groups: Vec<(String, Vec<(api::RoleId, String)>)> = Vec::new();
+    let mut other = Vec::new();
+    for role in roles {
+        let name = match categories.iter().find(|(id, _)| *id == role.0) {
+            Some((_, name)) => name,
+            None => {
+                other.push(role);
+                continue;
+            }
+        };
+        match groups
+            .iter()
+            .position(|(it, _)| it.eq_ignore_ascii_case(name))
+        {
+            Some(i) => groups[i].1.push(role),
+            None => groups.push((name.clone(), vec![role])),
+        }
+    }
+
+    groups.sort_by_key(|(name, _)| name.to_lowercase());
+    if !other.is_empty() {
+        groups.push((UNCATEGORIZED.to_string(), other));
+    }
+    for (_, group) in groups.iter_mut() {
+        group.sort_by_key(|(_, name)| name.to_lowercase());
+    }
+    groups
+}
+
+#[derive(poise::SlashChoiceParameter)]
+pub enum CategoryAction {
+    #[name = "Put the role in the category"]
+    Set,
+    #[name = "Take the role out of its category"]
+    Clear,
+    #[name = "Rename the category"]
+    Rename,
+}
+
+/// Show or change the categories roles are listed under
+#[poise::command(
+    slash_command,
+    category = "game",
+    required_permissions = "MANAGE_ROLES"
+)]
+pub async fn category(
+    ctx: Context<'_>,
+    #[description = "Change to make"] action: Option<CategoryAction>,
+    #[description = "Selected role"] role: Option<Role>,
+    #[description = "Category to put the role in or rename"] name: Option<String>,
+    #[description = "New name of the category"] new_name: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    if let Some(action) = action {
+        let changed = {
+            let mut categories = ctx.data().categories.lock().unwrap();
+            match (action, &role, &name, &new_name) {
+                (CategoryAction::Set, Some(role), Some(name), _) if is_valid_category(name) => {
+                    categories.set_category(guild_id, role.id, name);
+                    true
+                }
+                (CategoryAction::Clear, Some(role), _, _) => {
+                    categories.clear_category(guild_id, role.id).is_ok()
+                }
+                (CategoryAction::Rename, _, Some(name), Some(new_name))
+                    if is_valid_category(new_name) =>
+                {
+                    let renamed = categories.rename_category(guild_id, name, new_name) > 0;
+                    // panels of a category that doesn't exist keep their name
+                    if renamed {
+                        ctx.data()
+                            .panels
+                            .lock()
+                            .unwrap()
+                            .rename_category(guild_id.0, name, new_name);
+                    }
+                    renamed
+                }
+                _ => false,
+            }
+        };
+
+        if !changed {
+            ctx.send(|f| {
+                f.embed(unsuccessful_interaction(|f| {
+                    f.description("Failed to change the category! *Are the role and names right? Roles without a category are listed under Other already.*")
+                }))
+            })
+            .await?;
+            return Ok(());
+        }
+
+        info!(
+            "({}) {} changed the categories of the guild!",
+            guild_id,
+            ctx.author().id
+        );
+        save_table("categories", &ctx.data().categories.lock().unwrap());
+        save_table("panels", &ctx.data().panels.lock().unwrap());
+        refresh_panels(ctx.discord(), ctx.data(), guild_id).await;
+    }
+
+    let categories = ctx
+        .data()
+        .categories
+        .lock()
+        .unwrap()
+        .show_categories_of_guild(guild_id);
+    let roles: Vec<(api::RoleId, String)> = categories
+        .iter()
+        .filter_map(|(id, _)| {
+            RoleId::from(*id)
+                .to_role_cached(ctx.discord())
+                .map(|role| (*id, role.name))
+        })
+        .collect();
+
+    let mut message = MessageBuilder::new();
+    if roles.is_empty() {
+        message.push_italic("No categories yet!");
+    }
+    for (category, roles) in group_by_category(roles, &categories) {
+        message.push_bold_line_safe(category);
+        for (id, _) in roles {
+            message.push("• ").role(RoleId::from(id)).push_line("");
+        }
+    }
+    let message = message.build();
+
+    ctx.send(|f| f.embed(successful_interaction(|f| f.description(message))))
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::categories::*;
+
+    #[test]
+    pub fn test_group_by_category() {
+        let roles = vec![
+            (1, "Valorant".to_string()),
+            (2, "chess".to_string()),
+            (3, "CS".to_string()),
+            (4, "Minecraft".to_string()),
+        ];
+        let categories = vec![
+            (1, "FPS".to_string()),
+            (2, "Board games".to_string()),
+            (3, "fps".to_string()),
+        ];
+        assert_eq!(
+            group_by_category(roles, &categories),
+            vec![
+                ("Board games".to_string(), vec![(2, "chess".to_string())]),
+                (
+                    "FPS".to_string(),
+                    vec![(3, "CS".to_string()), (1, "Valorant".to_string())]
+                ),
+                ("Other".to_string(), vec![(4, "Minecraft".to_string())]),
+            ]
+        );
+        assert!(group_by_category(Vec::new(), &categories).is_empty());
+    }
+
+    #[test]
+    pub fn test_is_valid_category() {
+        assert!(is_valid_category("FPS"));
+        assert!(is_valid_category("Others"));
+        assert!(!is_valid_category(" other "));
+        assert!(!is_valid_category(" "));
+    }
+}
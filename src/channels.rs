@@ -0,0 +1,210 @@
+use log::info;
+use poise::serenity_prelude::{ChannelId, GuildChannel, MessageBuilder};
+use serde_derive::{Deserialize, Serialize};
+use tqdb::{remove, search};
+
+use crate::api::{GuildId, Snowflake, Table};
+use crate::util::*;
+use crate::{Context, Error};
+
+pub type ChannelSnowflake = Snowflake;
+
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone, Copy)]
+pub enum ChannelRuleKind {
+    Allow,
+    Deny,
+    Redirect(ChannelSnowflake),
+}
+
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+pub struct ChannelRule {
+    guild_id: GuildId,
+    channel_id: ChannelSnowflake,
+    rule: ChannelRuleKind,
+}
+
+impl Table<ChannelRule> {
+    pub fn set_rule<G: Into<GuildId>, C: Into<ChannelSnowflake>>(
+        &mut self,
+        guild_id: G,
+        channel_id: C,
+        rule: ChannelRuleKind,
+    ) {
+        let guild_id = guild_id.into();
+        let channel_id = channel_id.into();
+        self.clear_rule(guild_id, channel_id);
+        let _ = self.0.insert_unique(ChannelRule {
+            guild_id,
+            channel_id,
+            rule,
+        });
+    }
+
+    pub fn clear_rule<G: Into<GuildId>, C: Into<ChannelSnowflake>>(
+        &mut self,
+        guild_id: G,
+        channel_id: C,
+    ) -> Option<ChannelRuleKind> {
+        let guild_id = guild_id.into();
+        let channel_id = channel_id.into();
+        remove!(&mut self.0 => move |it: &ChannelRule| it.guild_id == guild_id && it.channel_id == channel_id)
+            .next()
+            .map(|it| it.rule)
+    }
+
+    pub fn show_rules_of_guild<G: Into<GuildId>>(
+        &self,
+        guild_id: G,
+    ) -> Vec<(ChannelSnowflake, ChannelRuleKind)> {
+        let guild_id = guild_id.into();
+        search!(&self.0 => move |it: &ChannelRule| it.guild_id == guild_id)
+            .map(|it| (it.channel_id, it.rule))
+            .collect()
+    }
+}
+
+/// What to do with role pings in a channel.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum ChannelDecision {
+    Notify,
+    Ignore,
+    Redirect(ChannelSnowflake),
+}
+
+/// Decide what to do with pings in a channel.
+///
+/// Once any channel is allowed, every channel that isn't allowed is ignored.
+pub fn channel_decision(
+    rules: &[(ChannelSnowflake, ChannelRuleKind)],
+    channel_id: ChannelSnowflake,
+) -> ChannelDecision {
+    match rules.iter().find(|(id, _)| *id == channel_id) {
+        Some((_, ChannelRuleKind::Allow)) => ChannelDecision::Notify,
+        Some((_, ChannelRuleKind::Deny)) => ChannelDecision::Ignore,
+        Some((_, ChannelRuleKind::Redirect(target))) => ChannelDecision::Redirect(*target),
+        None if rules.iter().any(|(_, r)| *r == ChannelRuleKind::Allow) => ChannelDecision::Ignore,
+        None => ChannelDecision::Notify,
+    }
+}
+
+#[derive(poise::SlashChoiceParameter)]
+pub enum ChannelAction {
+    #[name = "Allow pings"]
+    Allow,
+    #[name = "Ignore pings"]
+    Deny,
+    #[name = "Redirect pings to another channel"]
+    Redirect,
+    #[name = "Remove the rule"]
+    Clear,
+}
+
+/// Show or change where role pings create threads
+#[poise::command(
+    slash_command,
+    category = "game",
+    required_permissions = "MANAGE_CHANNELS"
+)]
+pub async fn channel(
+    ctx: Context<'_>,
+    #[description = "Selected channel"] channel: Option<GuildChannel>,
+    #[description = "Change to make"] action: Option<ChannelAction>,
+    #[description = "Channel to redirect to"] redirect_to: Option<GuildChannel>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    if let (Some(channel), Some(action)) = (&channel, action) {
+        let rule = match (action, &redirect_to) {
+            (ChannelAction::Allow, _) => Some(ChannelRuleKind::Allow),
+            (ChannelAction::Deny, _) => Some(ChannelRuleKind::Deny),
+            (ChannelAction::Redirect, Some(target)) => Some(ChannelRuleKind::Redirect(target.id.0)),
+            (ChannelAction::Redirect, None) => {
+                ctx.send(|f| {
+                    f.embed(unsuccessful_interaction(|f| {
+                        f.description("Select a channel to redirect to!")
+                    }))
+                })
+                .await?;
+                return Ok(());
+            }
+            (ChannelAction::Clear, _) => None,
+        };
+
+        let mut db = ctx.data().channels.lock().unwrap();
+        match rule {
+            Some(rule) => db.set_rule(guild_id, channel.id, rule),
+            None => {
+                db.clear_rule(guild_id, channel.id);
+            }
+        }
+        drop(db);
+
+        info!(
+            "({}) {} changed the rule of channel {}!",
+            guild_id,
+            ctx.author().id,
+            channel.id
+        );
+        save_table("channels", &ctx.data().channels.lock().unwrap());
+    }
+
+    let rules = ctx
+        .data()
+        .channels
+        .lock()
+        .unwrap()
+        .show_rules_of_guild(guild_id);
+
+    let mut message = MessageBuilder::new();
+    if rules.is_empty() {
+        message.push("Role pings create threads in every channel!");
+    }
+    for (id, rule) in rules {
+        message.channel(ChannelId::from(id));
+        match rule {
+            ChannelRuleKind::Allow => message.push_line(" allows pings"),
+            ChannelRuleKind::Deny => message.push_line(" ignores pings"),
+            ChannelRuleKind::Redirect(target) => message
+                .push(" redirects pings to ")
+                .channel(ChannelId::from(target))
+                .push_line(""),
+        };
+    }
+    let message = message.build();
+
+    ctx.send(|f| f.embed(successful_interaction(|f| f.description(message))))
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::channels::*;
+
+    #[test]
+    pub fn test_channel_decision_without_allowlist() {
+        let rules = vec![
+            (1, ChannelRuleKind::Deny),
+            (2, ChannelRuleKind::Redirect(3)),
+        ];
+        assert_eq!(channel_decision(&rules, 1), ChannelDecision::Ignore);
+        assert_eq!(channel_decision(&rules, 2), ChannelDecision::Redirect(3));
+        assert_eq!(channel_decision(&rules, 4), ChannelDecision::Notify);
+        assert_eq!(channel_decision(&[], 4), ChannelDecision::Notify);
+    }
+
+    #[test]
+    pub fn test_channel_decision_with_allowlist() {
+        let rules = vec![
+            (1, ChannelRuleKind::Allow),
+            (2, ChannelRuleKind::Redirect(1)),
+        ];
+        assert_eq!(channel_decision(&rules, 1), ChannelDecision::Notify);
+        assert_eq!(channel_decision(&rules, 2), ChannelDecision::Redirect(1));
+        assert_eq!(channel_decision(&rules, 3), ChannelDecision::Ignore);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::api::{self, GuildId};
+use crate::lfg::QueueId;
+use crate::sessions::{RsvpChoice, SessionId};
+use crate::suggestions::SuggestionAction;
+
+/// Prefix and version of every custom id this bot generates.
+const CUSTOM_ID_PREFIX: &str = "ib6";
+
+/// What pressing a component does, encoded in its custom id.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum ComponentAction {
+    /// Join the role when not subscribed, leave it otherwise
+    Toggle(api::RoleId),
+    Join(api::RoleId),
+    Leave(api::RoleId),
+    Rsvp(SessionId, RsvpChoice),
+    LfgJoin(QueueId),
+    LfgLeave(QueueId),
+    Suggestion(SuggestionAction),
+    /// A select menu of a role panel, by its position in the panel
+    Panel(usize),
+    /// Page buttons, answered by whoever sent the paginated message
+    PreviousPage,
+    NextPage,
+    /// Create a role despite similar ones, answered by the create command
+    CreateRole,
+}
+
+impl ComponentAction {
+    pub fn encode(&self) -> String {
+        let action = match self {
+            ComponentAction::Toggle(role_id) => format!("toggle:{}", role_id),
+            ComponentAction::Join(role_id) => format!("join:{}", role_id),
+            ComponentAction::Leave(role_id) => format!("leave:{}", role_id),
+            ComponentAction::Rsvp(session_id, choice) => {
+                format!("rsvp:{}:{}", session_id, choice.as_str())
+            }
+            ComponentAction::LfgJoin(queue_id) => format!("lfg:{}:join", queue_id),
+            ComponentAction::LfgLeave(queue_id) => format!("lfg:{}:leave", queue_id),
+            ComponentAction::Suggestion(SuggestionAction::Join(guild_id, role_id)) => {
+                format!("suggest:{}:{}", guild_id, role_id)
+            }
+            ComponentAction::Suggestion(SuggestionAction::OptOut) => "suggest:optout".into(),
+            ComponentAction::Panel(menu) => format!("panel:{}", menu),
+            ComponentAction::PreviousPage => "page:prev".into(),
+            ComponentAction::NextPage => "page:next".into(),
+            ComponentAction::CreateRole => "create".into(),
+        };
+        format!("{}:{}", CUSTOM_ID_PREFIX, action)
+    }
+
+    /// Decode a custom id, or `None` when it isn't one of ours.
+    pub fn decode(custom_id: &str) -> Option<Self> {
+        let parts: Vec<&str> = custom_id.split(':').collect();
+        match parts.as_slice() {
+            [CUSTOM_ID_PREFIX, action @ ..] => Self::decode_action(action),
+            _ => Self::decode_legacy(&parts),
+        }
+    }
+
+    fn decode_action(action: &[&str]) -> Option<Self> {
+        Some(match action {
+            ["toggle", role_id] => ComponentAction::Toggle(role_id.parse().ok()?),
+            ["join", role_id] => ComponentAction::Join(role_id.parse().ok()?),
+            ["leave", role_id] => ComponentAction::Leave(role_id.parse().ok()?),
+            ["rsvp", session_id, choice] => {
+                ComponentAction::Rsvp(session_id.parse().ok()?, RsvpChoice::from_str(choice)?)
+            }
+            ["lfg", queue_id, "join"] => ComponentAction::LfgJoin(queue_id.parse().ok()?),
+            ["lfg", queue_id, "leave"] => ComponentAction::LfgLeave(queue_id.parse().ok()?),
+            ["suggest", "optout"] => ComponentAction::Suggestion(SuggestionAction::OptOut),
+            ["suggest", guild_id, role_id] => ComponentAction::Suggestion(SuggestionAction::Join(
+                guild_id.parse::<GuildId>().ok()?,
+                role_id.parse().ok()?,
+            )),
+            ["panel", menu] => ComponentAction::Panel(menu.parse().ok()?),
+            ["page", "prev"] => ComponentAction::PreviousPage,
+            ["page", "next"] => ComponentAction::NextPage,
+            ["create"] => ComponentAction::CreateRole,
+            _ => return None,
+        })
+    }
+
+    /// Ids of components that were sent before they were versioned.
+    fn decode_legacy(parts: &[&str]) -> Option<Self> {
+        match parts {
+            [role_id] => Some(ComponentAction::Join(role_id.parse().ok()?)),
+            ["", role_id] => Some(ComponentAction::Leave(role_id.parse().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::components::*;
+
+    #[test]
+    pub fn test_component_action() {
+        for action in [
+            ComponentAction::Toggle(1),
+            ComponentAction::Join(2),
+            ComponentAction::Leave(3),
+            ComponentAction::Rsvp(12, RsvpChoice::Maybe),
+            ComponentAction::LfgJoin(3),
+            ComponentAction::LfgLeave(3),
+            ComponentAction::Suggestion(SuggestionAction::Join(1, 2)),
+            ComponentAction::Suggestion(SuggestionAction::OptOut),
+            ComponentAction::Panel(2),
+            ComponentAction::PreviousPage,
+            ComponentAction::NextPage,
+            ComponentAction::CreateRole,
+        ] {
+            assert_eq!(ComponentAction::decode(&action.encode()), Some(action));
+        }
+        assert_eq!(ComponentAction::Join(2).encode(), "ib6:join:2");
+        assert_eq!(
+            ComponentAction::Rsvp(4, RsvpChoice::Join).encode(),
+            "ib6:rsvp:4:join"
+        );
+    }
+
+    #[test]
+    pub fn test_component_action_unknown() {
+        assert_eq!(ComponentAction::decode("ib6:join:x"), None);
+        assert_eq!(ComponentAction::decode("ib6:join:2:3"), None);
+        assert_eq!(ComponentAction::decode("ib6:rsvp:12:never"), None);
+        assert_eq!(ComponentAction::decode("ib6:lfg:3:maybe"), None);
+        assert_eq!(ComponentAction::decode("ib6:suggest:1"), None);
+        assert_eq!(ComponentAction::decode("ib6:page:last"), None);
+        assert_eq!(ComponentAction::decode("ib7:join:2"), None);
+        assert_eq!(ComponentAction::decode("some-other-bot"), None);
+        assert_eq!(ComponentAction::decode(""), None);
+    }
+
+    #[test]
+    pub fn test_component_action_legacy() {
+        assert_eq!(
+            ComponentAction::decode("123"),
+            Some(ComponentAction::Join(123))
+        );
+        assert_eq!(
+            ComponentAction::decode(":123"),
+            Some(ComponentAction::Leave(123))
+        );
+    }
+}
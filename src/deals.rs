@@ -1,3 +1,4 @@
+use crate::metrics::ITAD_LATENCY;
 use crate::util::unsuccessful_interaction;
 use crate::{Context, Error};
 use dotenv as env;
@@ -167,7 +168,9 @@ async fn do_http_request<T: DeserializeOwned, S: AsRef<str>>(
     json_data_pointer: S,
 ) -> Option<T> {
     // first we have to get the plain identifier for the game
+    let timer = ITAD_LATENCY.start_timer();
     let body = reqwest::get(uri.as_ref()).await.ok()?.text().await.ok()?;
+    timer.observe_duration();
 
     // body is json
     let body: serde_json::Value = match serde_json::from_str(body.as_str()) {
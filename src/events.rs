@@ -1,17 +1,34 @@
-use std::str::FromStr;
-
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt};
 use log::{error, info};
+use poise::serenity::gateway::ConnectionStage;
 use poise::serenity_prelude::{
-    Activity, Context as SerenityContext, GuildId, Interaction,
+    Activity, ButtonStyle, ChannelId, Color, Context as SerenityContext, GuildId, Interaction,
     InteractionApplicationCommandCallbackDataFlags, InteractionResponseType, Mentionable, Message,
-    Ready, Role, RoleId, User, UserId,
+    MessageBuilder, MessageId, MessageUpdateEvent, Ready, Role, RoleId, User, UserId,
 };
-
-use crate::util::save_to_db;
+use std::collections::HashMap;
+
+use crate::aliases::triggered_roles;
+use crate::api::ApiError;
+use crate::channels::{channel_decision, ChannelDecision};
+use crate::components::ComponentAction;
+use crate::lfg::interaction_lfg;
+use crate::managed::check_game_role_id;
+use crate::metrics::{variant_name, ERRORS, THREADS_CREATED, THREAD_MEMBERS_ADDED};
+use crate::panels::{interaction_panel, refresh_panels};
+use crate::permissions::may_ping;
+use crate::sessions::interaction_rsvp;
+use crate::settings::NotificationStyle;
+use crate::stats::{record, RoleEventKind};
+use crate::suggestions::{interaction_suggestion, on_presence_update};
+use crate::template::{render_thread_name, ThreadNameContext, DEFAULT_THREAD_NAME};
+use crate::util::{join_within_limit, save_roles, save_table, MESSAGE_LIMIT};
+use crate::voice::on_voice_state_update;
 use crate::{Data, Error};
 
 pub async fn on_error(error: poise::FrameworkError<'_, Data, Error>) {
+    ERRORS.with_label_values(&[variant_name(&error)]).inc();
     match error {
         poise::FrameworkError::Setup { error } => panic!("Failed to start bot: {:?}", error),
         poise::FrameworkError::Command { error, ctx } => {
@@ -37,9 +54,40 @@ pub async fn event_listener(
             guild_id,
             removed_role_id,
             removed_role_data_if_available,
-        } => on_guild_role_delete(guild_id, removed_role_id, removed_role_data_if_available),
-        poise::Event::Ready { data_about_bot } => on_ready(ctx, data_about_bot).await,
+        } => {
+            on_guild_role_delete(
+                user_data,
+                guild_id,
+                removed_role_id,
+                removed_role_data_if_available,
+            );
+            refresh_panels(ctx, user_data, *guild_id).await;
+        }
+        poise::Event::Ready { data_about_bot } => {
+            user_data.health.lock().unwrap().connected = true;
+            on_ready(ctx, data_about_bot).await
+        }
+        poise::Event::CacheReady { .. } => {
+            user_data.health.lock().unwrap().cache_ready = true;
+        }
+        poise::Event::Resume { .. } => {
+            user_data.health.lock().unwrap().connected = true;
+        }
+        poise::Event::ShardStageUpdate { update } => {
+            user_data.health.lock().unwrap().connected = update.new == ConnectionStage::Connected;
+        }
         poise::Event::Message { new_message } => on_message(&ctx, user_data, new_message).await?,
+        poise::Event::MessageUpdate {
+            old_if_available,
+            event,
+            ..
+        } => on_message_update(ctx, user_data, old_if_available, event).await?,
+        poise::Event::VoiceStateUpdate { old, new, .. } => {
+            on_voice_state_update(ctx, user_data, old, new).await?
+        }
+        poise::Event::PresenceUpdate { new_data } => {
+            on_presence_update(ctx, user_data, new_data).await?
+        }
         poise::Event::InteractionCreate { interaction } => {
             on_interaction_create(ctx, user_data, interaction).await?
         }
@@ -49,10 +97,12 @@ pub async fn event_listener(
     Ok(())
 }
 
-pub async fn interaction_leave_role(
+/// Join, leave or toggle a role from a button, offering to undo the change.
+pub async fn interaction_role_action(
     ctx: &SerenityContext,
     user_data: &Data,
     interaction: &Interaction,
+    action: ComponentAction,
 ) -> Result<(), poise::serenity_prelude::SerenityError> {
     let m = match interaction.clone().message_component() {
         Some(m) => m,
@@ -64,36 +114,102 @@ pub async fn interaction_leave_role(
         _ => return Ok(()),
     };
 
-    let role_id = u64::from_str(&m.data.custom_id[1..])?;
-
-    let response =
-        match user_data
-            .0
-            .lock()
-            .unwrap()
-            .remove_user_from_role(guild_id.0, role_id, m.user.id.0)
-        {
-            Ok(_) => {
-                info!("({}) {} left {}!", guild_id, m.user.id, role_id);
-                "✅ Removed you from the role!"
-            }
-            Err(_) => "❌ Failed to remove you to the role. *Did you already leave?*",
+    let role_id = match action {
+        ComponentAction::Toggle(role_id)
+        | ComponentAction::Join(role_id)
+        | ComponentAction::Leave(role_id) => role_id,
+        _ => return Ok(()),
+    };
+    // leaving is always allowed, even roles that stopped being game roles
+    let refusal = check_game_role_id(ctx, user_data, role_id).err();
+
+    // a toggle checks and changes membership under the same lock, so double clicks can't race
+    let (join, choice) = {
+        let mut roles = user_data.roles.lock().unwrap();
+        let join = match action {
+            ComponentAction::Toggle(_) => !roles
+                .show_users_of_role(guild_id.0, role_id)
+                .contains(&&m.user.id.0),
+            ComponentAction::Join(_) => true,
+            _ => false,
+        };
+        let choice = if join && refusal.is_some() {
+            Err(ApiError::Insertion)
+        } else if join {
+            roles.add_user_to_role(guild_id.0, role_id, m.user.id.0)
+        } else {
+            roles
+                .remove_user_from_role(guild_id.0, role_id, m.user.id.0)
+                .map(|_| ())
         };
+        (join, choice)
+    };
 
-    save_to_db(user_data);
+    let role = RoleId::from(role_id);
+    let (response, undo) = match (choice, join) {
+        (Ok(_), true) => {
+            info!("({}) {} joined {}!", guild_id, m.user.id, role_id);
+            record(user_data, guild_id, role_id, m.user.id, RoleEventKind::Join);
+            (
+                MessageBuilder::new()
+                    .push("✅ You joined ")
+                    .role(role)
+                    .push("!")
+                    .build(),
+                Some(ComponentAction::Leave(role_id)),
+            )
+        }
+        (Ok(_), false) => {
+            info!("({}) {} left {}!", guild_id, m.user.id, role_id);
+            record(
+                user_data,
+                guild_id,
+                role_id,
+                m.user.id,
+                RoleEventKind::Leave,
+            );
+            (
+                MessageBuilder::new()
+                    .push("✅ You left ")
+                    .role(role)
+                    .push("!")
+                    .build(),
+                Some(ComponentAction::Join(role_id)),
+            )
+        }
+        (Err(_), true) => match refusal {
+            Some(refusal) => (format!("❌ You can't join this role! {}", refusal), None),
+            None => ("❌ You are already in this role!".to_string(), None),
+        },
+        (Err(_), false) => ("❌ You are not in this role!".to_string(), None),
+    };
+
+    save_roles(user_data);
 
     m.create_interaction_response(ctx, |f| {
         f.kind(InteractionResponseType::ChannelMessageWithSource)
             .interaction_response_data(|f| {
                 f.content(response)
-                    .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                    .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
+                if let Some(undo) = undo {
+                    f.components(|f| {
+                        f.create_action_row(|f| {
+                            f.create_button(|f| {
+                                f.custom_id(undo.encode())
+                                    .style(ButtonStyle::Secondary)
+                                    .label("Undo")
+                            })
+                        })
+                    });
+                }
+                f
             })
     })
     .await?;
     Ok(())
 }
 
-pub async fn interaction_join_role(
+pub async fn on_interaction_create(
     ctx: &SerenityContext,
     user_data: &Data,
     interaction: &Interaction,
@@ -103,59 +219,83 @@ pub async fn interaction_join_role(
         None => return Ok(()),
     };
 
-    let guild_id = match m.guild_id {
-        Some(id) => id,
-        _ => return Ok(()),
+    let action = match ComponentAction::decode(&m.data.custom_id) {
+        Some(action) => action,
+        None => {
+            info!("Ignored unknown component ({})", m.data.custom_id);
+            return Ok(());
+        }
     };
 
-    let role_id = u64::from_str(m.data.custom_id.as_str()).expect("Custom id was not u64.");
+    match action {
+        ComponentAction::Toggle(_) | ComponentAction::Join(_) | ComponentAction::Leave(_) => {
+            interaction_role_action(ctx, user_data, interaction, action).await
+        }
+        ComponentAction::Rsvp(session_id, choice) => {
+            interaction_rsvp(ctx, user_data, interaction, session_id, choice).await
+        }
+        ComponentAction::LfgJoin(id) => {
+            interaction_lfg(ctx, user_data, interaction, id, true).await
+        }
+        ComponentAction::LfgLeave(id) => {
+            interaction_lfg(ctx, user_data, interaction, id, false).await
+        }
+        ComponentAction::Suggestion(action) => {
+            interaction_suggestion(ctx, user_data, interaction, action).await
+        }
+        ComponentAction::Panel(_) => interaction_panel(ctx, user_data, interaction).await,
+        // the command that sent these is waiting for them
+        ComponentAction::PreviousPage | ComponentAction::NextPage | ComponentAction::CreateRole => {
+            Ok(())
+        }
+    }
+}
 
-    let mut added = false;
-    let response =
-        match user_data
-            .0
-            .lock()
-            .unwrap()
-            .add_user_to_role(guild_id.0, role_id, m.user.id.0)
-        {
-            Ok(_) => {
-                info!("({}) {} joined {}!", guild_id, m.user.id, role_id);
-                added = true;
-                format!("✅ Added {} to the role!", m.user.mention())
-            }
-            Err(_) => "❌ Failed to add you to the role. *Are you already in it?*".to_string(),
-        };
+/// Roles that were already notified for a message, so edits only notify newly mentioned roles.
+#[derive(Debug)]
+pub struct NotifiedMessage {
+    pub thread_id: Option<ChannelId>,
+    pub roles: Vec<RoleId>,
+    pub at: DateTime<Utc>,
+}
 
-    save_to_db(user_data);
+/// How many messages are remembered at most, the oldest are forgotten first.
+const NOTIFIED_LIMIT: usize = 1000;
 
-    m.create_interaction_response(ctx, |f| {
-        f.kind(InteractionResponseType::ChannelMessageWithSource)
-            .interaction_response_data(|f| {
-                f.content(response);
-                if !added {
-                    f.flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL);
-                }
-                f
-            })
-    })
-    .await?;
-    Ok(())
-}
+/// How many days messages are remembered.
+const NOTIFIED_DAYS: i64 = 1;
 
-pub async fn on_interaction_create(
-    ctx: &SerenityContext,
-    user_data: &Data,
-    interaction: &Interaction,
-) -> Result<(), poise::serenity_prelude::SerenityError> {
-    let m = match interaction.clone().message_component() {
-        Some(m) => m,
-        None => return Ok(()),
-    };
+/// Unix timestamp of the first second of 2015, where snowflakes start counting.
+const DISCORD_EPOCH: i64 = 1_420_070_400;
 
-    match m.data.custom_id.as_str().get(..1) {
-        Some(":") => interaction_leave_role(ctx, user_data, interaction).await,
-        Some(_) => interaction_join_role(ctx, user_data, interaction).await,
-        _ => Ok(()),
+/// Remember that roles were notified in a thread on a message.
+///
+/// Messages are forgotten after [`NOTIFIED_DAYS`], or once there are too many.
+pub fn remember_notified(
+    notified: &mut HashMap<MessageId, NotifiedMessage>,
+    message_id: MessageId,
+    thread_id: ChannelId,
+    roles: &[RoleId],
+    now: DateTime<Utc>,
+) {
+    notified.retain(|_, n| now - n.at < chrono::Duration::days(NOTIFIED_DAYS));
+    let entry = notified
+        .entry(message_id)
+        .or_insert_with(|| NotifiedMessage {
+            thread_id: None,
+            roles: Vec::new(),
+            at: now,
+        });
+    entry.thread_id = Some(thread_id);
+    entry.roles.extend(roles.iter().copied());
+
+    if notified.len() > NOTIFIED_LIMIT {
+        let mut ages: Vec<(DateTime<Utc>, MessageId)> =
+            notified.iter().map(|(id, n)| (n.at, *id)).collect();
+        ages.sort();
+        for (_, id) in ages.into_iter().take(notified.len() - NOTIFIED_LIMIT) {
+            notified.remove(&id);
+        }
     }
 }
 
@@ -169,71 +309,312 @@ pub async fn on_message(
         None => return Ok(()),
     };
 
-    let roles = new_message
-        .mention_roles
+    let author_roles: Vec<u64> = new_message
+        .member
+        .as_ref()
+        .map(|m| m.roles.iter().map(|r| r.0).collect())
+        .unwrap_or_default();
+
+    let mut mention_roles = new_message.mention_roles.clone();
+    mention_roles.extend(triggered_roles(
+        ctx,
+        user_data,
+        guild_id.0,
+        &new_message.content,
+    ));
+    mention_roles.sort();
+    mention_roles.dedup();
+
+    notify_roles(
+        ctx,
+        user_data,
+        guild_id,
+        new_message,
+        &author_roles,
+        &mention_roles,
+    )
+    .await
+}
+
+/// Unix timestamp of when a message was sent, from its snowflake.
+pub fn sent_at(message_id: MessageId) -> i64 {
+    (message_id.0 >> 22) as i64 / 1000 + DISCORD_EPOCH
+}
+
+/// Whether every notification of a message would still be remembered, so edits can't repeat them.
+pub fn edits_tracked(message_id: MessageId, started: i64, now: i64) -> bool {
+    let sent = sent_at(message_id);
+    sent >= started && now - sent < NOTIFIED_DAYS * 24 * 60 * 60
+}
+
+pub async fn on_message_update(
+    ctx: &SerenityContext,
+    user_data: &Data,
+    old: &Option<Message>,
+    event: &MessageUpdateEvent,
+) -> Result<(), poise::serenity_prelude::SerenityError> {
+    let guild_id = match event.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let mut mention_roles = event.mention_roles.clone().unwrap_or_default();
+    if let Some(content) = &event.content {
+        mention_roles.extend(triggered_roles(ctx, user_data, guild_id.0, content));
+    }
+    // roles of the old content were handled when it was sent
+    if let Some(old) = old {
+        let mut old_roles = old.mention_roles.clone();
+        old_roles.extend(triggered_roles(ctx, user_data, guild_id.0, &old.content));
+        mention_roles.retain(|id| !old_roles.contains(id));
+    }
+    mention_roles.sort();
+    mention_roles.dedup();
+
+    let (remembered, new_roles): (bool, Vec<RoleId>) = {
+        let notified = user_data.notified.lock().unwrap();
+        match notified.get(&event.id) {
+            Some(n) => (
+                true,
+                mention_roles
+                    .iter()
+                    .copied()
+                    .filter(|id| !n.roles.contains(id))
+                    .collect(),
+            ),
+            // sent before a restart or forgotten, its roles may already have been notified
+            None if !edits_tracked(event.id, user_data.started, Utc::now().timestamp()) => {
+                return Ok(())
+            }
+            None => (false, mention_roles),
+        }
+    };
+
+    if new_roles.is_empty() {
+        return Ok(());
+    }
+
+    // messages fetched over http know neither their guild nor their author's roles
+    let message = event.channel_id.message(ctx, event.id).await?;
+    if !remembered && message.thread.is_some() {
+        return Ok(());
+    }
+    let author_roles: Vec<u64> = guild_id
+        .member(ctx, message.author.id)
+        .await
+        .map(|m| m.roles.iter().map(|r| r.0).collect())
+        .unwrap_or_default();
+
+    notify_roles(
+        ctx,
+        user_data,
+        guild_id,
+        &message,
+        &author_roles,
+        &new_roles,
+    )
+    .await
+}
+
+/// Subscribers to add to a thread, leaving out the author and bots.
+pub fn thread_recipients(subscribers: &[(UserId, bool)], author_id: UserId) -> Vec<UserId> {
+    let mut recipients: Vec<UserId> = subscribers
         .iter()
-        .map(|id| id.to_role_cached(&ctx).unwrap().name);
+        .filter(|(id, bot)| *id != author_id && !bot)
+        .map(|(id, _)| *id)
+        .collect();
+    recipients.sort();
+    recipients
+}
 
-    if roles.len() == 0 {
+/// Notify the subscribers of the mentioned roles in a thread on the message.
+async fn notify_roles(
+    ctx: &SerenityContext,
+    user_data: &Data,
+    guild_id: GuildId,
+    new_message: &Message,
+    author_roles: &[u64],
+    mention_roles: &[RoleId],
+) -> Result<(), poise::serenity_prelude::SerenityError> {
+    let decision = channel_decision(
+        &user_data
+            .channels
+            .lock()
+            .unwrap()
+            .show_rules_of_guild(guild_id.0),
+        new_message.channel_id.0,
+    );
+    if decision == ChannelDecision::Ignore {
         return Ok(());
     }
 
-    let userids: Vec<_> = new_message
-        .mention_roles
+    let mention_roles: Vec<RoleId> = mention_roles
         .iter()
-        .flat_map(|id| {
-            user_data
-                .0
+        .copied()
+        .filter(|id| {
+            let subscribed = user_data
+                .roles
                 .lock()
                 .unwrap()
                 .show_users_of_role(guild_id.0, id.0)
-                .into_iter()
-                .copied()
-                .map(UserId::from)
-                .collect::<Vec<_>>()
+                .contains(&&new_message.author.id.0);
+            let allowed = may_ping(
+                &user_data
+                    .permissions
+                    .lock()
+                    .unwrap()
+                    .show_rules_of_role(guild_id.0, id.0),
+                new_message.author.id.0,
+                author_roles,
+                subscribed,
+            );
+            if !allowed {
+                info!(
+                    "({}) {} is not allowed to ping {}!",
+                    guild_id, new_message.author.id, id
+                );
+            }
+            allowed
         })
         .collect();
 
-    if userids.is_empty() {
+    // roles missing from the cache were deleted, or stale aliases point at them
+    let (mention_roles, roles): (Vec<RoleId>, Vec<String>) = mention_roles
+        .into_iter()
+        .filter_map(|id| id.to_role_cached(&ctx).map(|role| (id, role.name)))
+        .unzip();
+
+    if roles.is_empty() {
         return Ok(());
     }
 
-    let message = new_message
-        .channel_id
-        .create_public_thread(&ctx, new_message.id, |f| {
-            f.name(format!(
-                "[{}] {} Discussion",
-                Utc::now().format("%v"),
-                roles.collect::<Vec<String>>().join(", ")
-            ))
-            .auto_archive_duration(1440)
-            .kind(poise::serenity_prelude::model::channel::ChannelType::PublicThread)
-        })
+    let thread_id = user_data
+        .notified
+        .lock()
+        .unwrap()
+        .get(&new_message.id)
+        .and_then(|n| n.thread_id);
+    for id in mention_roles.iter() {
+        record(
+            user_data,
+            guild_id,
+            *id,
+            new_message.author.id,
+            RoleEventKind::Ping,
+        );
+    }
+
+    let subscribers: Vec<UserId> = user_data
+        .roles
+        .lock()
+        .unwrap()
+        .show_users_of_roles(
+            guild_id.0,
+            &mention_roles.iter().map(|id| id.0).collect::<Vec<_>>(),
+        )
+        .into_iter()
+        .copied()
+        .map(UserId::from)
+        .collect();
+
+    // users missing from the cache are fetched, bots among them would be added otherwise
+    let subscribers: Vec<(UserId, bool)> = stream::iter(subscribers)
+        .map(|id| async move { (id, id.to_user(ctx).await.map_or(false, |u| u.bot)) })
+        .buffer_unordered(THREAD_MEMBER_CONCURRENCY)
+        .collect()
         .await;
+    let userids = thread_recipients(&subscribers, new_message.author.id);
 
-    if message.is_err() {
-        error!(
-            "Failed to make thread for mention in message ({})! {}",
-            new_message.id,
-            message.unwrap_err()
-        );
+    if userids.is_empty() {
         return Ok(());
     }
 
-    let thread = message.unwrap();
+    let settings = user_data
+        .settings
+        .lock()
+        .unwrap()
+        .settings_of_guild(guild_id.0);
 
-    for id in userids.iter() {
-        thread.id.add_thread_member(&ctx, *id).await?;
-    }
+    let thread_id = match thread_id {
+        Some(id) => id,
+        None => {
+            let channel = new_message.channel_id.name(ctx).await.unwrap_or_default();
+            let thread_name_context = ThreadNameContext {
+                now: Utc::now().with_timezone(&settings.timezone()),
+                roles: &roles,
+                author: &new_message.author.name,
+                channel: &channel,
+                content: &new_message.content,
+            };
+            let thread_name = render_thread_name(settings.thread_name(), &thread_name_context)
+                .or_else(|_| render_thread_name(DEFAULT_THREAD_NAME, &thread_name_context))
+                .unwrap_or_default();
+
+            let (thread_channel_id, thread_message_id) = match decision {
+                ChannelDecision::Redirect(target) => {
+                    let target = ChannelId::from(target);
+                    let anchor = target
+                        .send_message(&ctx, |m| {
+                            m.content(
+                                MessageBuilder::new()
+                                    .user(&new_message.author)
+                                    .push(" pinged ")
+                                    .push_safe(roles.join(", "))
+                                    .push(" in ")
+                                    .channel(new_message.channel_id)
+                                    .push_line("!")
+                                    .push(new_message.link())
+                                    .build(),
+                            )
+                            .allowed_mentions(|f| f.empty_parse())
+                        })
+                        .await?;
+                    (target, anchor.id)
+                }
+                _ => (new_message.channel_id, new_message.id),
+            };
+
+            let message = thread_channel_id
+                .create_public_thread(&ctx, thread_message_id, |f| {
+                    f.name(thread_name)
+                        .auto_archive_duration(1440)
+                        .kind(poise::serenity_prelude::model::channel::ChannelType::PublicThread)
+                })
+                .await;
+
+            if message.is_err() {
+                error!(
+                    "Failed to make thread for mention in message ({})! {}",
+                    new_message.id,
+                    message.unwrap_err()
+                );
+                return Ok(());
+            }
 
-    thread
-        .send_message(&ctx, |m| m.content("@everyone"))
-        .await?;
+            THREADS_CREATED.inc();
+            message.unwrap().id
+        }
+    };
+    remember_notified(
+        &mut user_data.notified.lock().unwrap(),
+        new_message.id,
+        thread_id,
+        &mention_roles,
+        Utc::now(),
+    );
+
+    populate_thread(
+        ctx,
+        thread_id,
+        settings.notification,
+        &userids,
+        &roles.join(", "),
+    )
+    .await?;
 
     info!(
         "Notified roles ({}) in guild ({})!",
-        new_message
-            .mention_roles
+        mention_roles
             .iter()
             .map(|it| it.to_string())
             .collect::<Vec<String>>()
@@ -243,6 +624,121 @@ pub async fn on_message(
     Ok(())
 }
 
+/// Add users to a thread and tell them about it, returning the users that were notified.
+pub async fn populate_thread(
+    ctx: &SerenityContext,
+    thread_id: ChannelId,
+    style: NotificationStyle,
+    users: &[UserId],
+    subject: &str,
+) -> Result<Vec<UserId>, poise::serenity_prelude::SerenityError> {
+    let failed = add_thread_members(ctx, thread_id, users.iter().copied()).await;
+    if !failed.is_empty() {
+        error!(
+            "Failed to add users ({}) to thread ({})!",
+            failed
+                .iter()
+                .map(|it| it.to_string())
+                .collect::<Vec<String>>()
+                .join(" "),
+            thread_id
+        );
+    }
+
+    let notified: Vec<UserId> = users
+        .iter()
+        .copied()
+        .filter(|id| !failed.contains(id))
+        .collect();
+
+    announce(ctx, thread_id, style, &notified, subject).await?;
+    Ok(notified)
+}
+
+/// Discord refuses allowed mentions with more users than this.
+const ALLOWED_MENTIONS_LIMIT: usize = 100;
+
+/// Split mentions into messages, each with the users it mentions.
+pub fn mention_chunks(users: &[UserId]) -> Vec<(String, Vec<UserId>)> {
+    let mut chunks = Vec::new();
+    for users in users.chunks(ALLOWED_MENTIONS_LIMIT) {
+        let mentions: Vec<String> = users.iter().map(|id| id.mention().to_string()).collect();
+        let mut users = users.iter().copied();
+        for chunk in join_within_limit(&mentions, MESSAGE_LIMIT) {
+            let ids = users.by_ref().take(chunk.split(' ').count()).collect();
+            chunks.push((chunk, ids));
+        }
+    }
+    chunks
+}
+
+/// Tell users added to a thread about it, in the style the guild prefers.
+async fn announce(
+    ctx: &SerenityContext,
+    thread_id: ChannelId,
+    style: NotificationStyle,
+    notified: &[UserId],
+    subject: &str,
+) -> Result<(), poise::serenity_prelude::SerenityError> {
+    match style {
+        NotificationStyle::Mentions => {
+            for (chunk, users) in mention_chunks(notified) {
+                thread_id
+                    .send_message(&ctx, |m| {
+                        m.content(chunk).allowed_mentions(|f| f.users(users))
+                    })
+                    .await?;
+            }
+        }
+        NotificationStyle::Summary => {
+            thread_id
+                .send_message(&ctx, |m| {
+                    m.embed(|f| {
+                        f.color(Color::DARK_GREEN)
+                            .title("🔔 New discussion!")
+                            .description(format!(
+                                "Added {} subscribers of {} to this thread.",
+                                notified.len(),
+                                subject
+                            ))
+                    })
+                })
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// How many thread members are added at once.
+///
+/// Serenity waits out Discord's rate limits for us, this only keeps us from queueing every request at once.
+const THREAD_MEMBER_CONCURRENCY: usize = 5;
+
+/// Add users to a thread, returning the users that could not be added.
+pub async fn add_thread_members(
+    ctx: &SerenityContext,
+    thread_id: ChannelId,
+    users: impl IntoIterator<Item = UserId>,
+) -> Vec<UserId> {
+    stream::iter(users)
+        .map(|id| async move { (id, thread_id.add_thread_member(ctx, id).await) })
+        .buffer_unordered(THREAD_MEMBER_CONCURRENCY)
+        .filter_map(|(id, result)| async move {
+            match result {
+                Ok(_) => {
+                    THREAD_MEMBERS_ADDED.inc();
+                    None
+                }
+                Err(e) => {
+                    error!("Failed to add {} to thread ({})! {}", id, thread_id, e);
+                    Some(id)
+                }
+            }
+        })
+        .collect()
+        .await
+}
+
 // TODO
 pub fn on_guild_member_removal(guild_id: &GuildId, user: &User) {
     info!(
@@ -264,6 +760,7 @@ pub async fn on_ready(ctx: &SerenityContext, data_about_bot: &Ready) {
 
 // TODO
 pub fn on_guild_role_delete(
+    user_data: &Data,
     guild_id: &GuildId,
     removed_role_id: &RoleId,
     removed_role_data_if_available: &Option<Role>,
@@ -276,4 +773,98 @@ pub fn on_guild_role_delete(
     } else {
         info!("Guild ({}) role deleted ({})", guild_id, removed_role_id)
     }
+
+    let cleared = user_data
+        .categories
+        .lock()
+        .unwrap()
+        .clear_category(guild_id.0, removed_role_id.0);
+    if cleared.is_ok() {
+        save_table("categories", &user_data.categories.lock().unwrap());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::*;
+
+    #[test]
+    pub fn test_thread_recipients() {
+        let subscribers = vec![
+            (UserId(3), false),
+            (UserId(1), false),
+            (UserId(2), true),
+            (UserId(4), false),
+        ];
+        assert_eq!(
+            thread_recipients(&subscribers, UserId(4)),
+            vec![UserId(1), UserId(3)]
+        );
+        assert!(thread_recipients(&[(UserId(1), false)], UserId(1)).is_empty());
+    }
+
+    #[test]
+    pub fn test_edits_tracked() {
+        let id = MessageId(175928847299117063);
+        assert_eq!(sent_at(id), 1462015105);
+        assert!(edits_tracked(id, 1462015000, 1462015200));
+        // sent before the bot started
+        assert!(!edits_tracked(id, 1462015200, 1462015300));
+        // sent longer ago than messages are remembered
+        assert!(!edits_tracked(
+            id,
+            1462015000,
+            1462015105 + 2 * 24 * 60 * 60
+        ));
+    }
+
+    #[test]
+    pub fn test_remember_notified() {
+        let now = Utc::now();
+        let mut notified = HashMap::new();
+        notified.insert(
+            MessageId(1),
+            NotifiedMessage {
+                thread_id: None,
+                roles: vec![RoleId(1)],
+                at: now - chrono::Duration::days(2),
+            },
+        );
+        remember_notified(&mut notified, MessageId(2), ChannelId(5), &[RoleId(2)], now);
+        remember_notified(&mut notified, MessageId(2), ChannelId(5), &[RoleId(3)], now);
+        assert!(!notified.contains_key(&MessageId(1)));
+        assert_eq!(notified[&MessageId(2)].thread_id, Some(ChannelId(5)));
+        assert_eq!(notified[&MessageId(2)].roles, vec![RoleId(2), RoleId(3)]);
+
+        for i in 0..NOTIFIED_LIMIT as u64 + 10 {
+            let at = now + chrono::Duration::seconds(i as i64);
+            remember_notified(&mut notified, MessageId(100 + i), ChannelId(5), &[], at);
+        }
+        assert_eq!(notified.len(), NOTIFIED_LIMIT);
+        assert!(!notified.contains_key(&MessageId(2)));
+        assert!(notified.contains_key(&MessageId(100 + NOTIFIED_LIMIT as u64 + 9)));
+    }
+
+    #[test]
+    pub fn test_mention_chunks() {
+        let users: Vec<UserId> = (1..=250).map(UserId).collect();
+        let chunks = mention_chunks(&users);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0].1, users[..100].to_vec());
+        assert_eq!(chunks[2].1, users[200..].to_vec());
+        assert!(chunks[0].0.starts_with("<@1> <@2> "));
+
+        let users: Vec<UserId> = (0..100)
+            .map(|i| UserId(100_000_000_000_000_000 + i))
+            .collect();
+        let chunks = mention_chunks(&users);
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.iter().all(|(chunk, _)| chunk.len() <= MESSAGE_LIMIT));
+        assert!(chunks
+            .iter()
+            .all(|(chunk, ids)| chunk.split(' ').count() == ids.len()));
+        let ids: Vec<UserId> = chunks.into_iter().flat_map(|(_, ids)| ids).collect();
+        assert_eq!(ids, users);
+        assert!(mention_chunks(&[]).is_empty());
+    }
 }
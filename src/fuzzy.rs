@@ -0,0 +1,161 @@
+/// Discord shows at most this many autocomplete suggestions.
+pub const SUGGESTIONS: usize = 25;
+
+/// How well a name matches what was typed so far, lower is better.
+///
+/// Exact matches come first, then prefixes, word prefixes, substrings and
+/// finally names containing the typed letters in order.
+pub fn match_score(query: &str, name: &str) -> Option<usize> {
+    let query = query.trim().to_lowercase();
+    let name = name.to_lowercase();
+    if query.is_empty() || name == query {
+        return Some(0);
+    }
+    if name.starts_with(&query) {
+        return Some(1);
+    }
+    if name.split_whitespace().any(|word| word.starts_with(&query)) {
+        return Some(2);
+    }
+    if name.contains(&query) {
+        return Some(3);
+    }
+
+    // letters in order, counting the ones skipped in between
+    let mut gaps = 0;
+    let mut letters = name.chars();
+    for c in query.chars() {
+        loop {
+            match letters.next() {
+                Some(it) if it == c => break,
+                Some(_) => gaps += 1,
+                None => return None,
+            }
+        }
+    }
+    Some(4 + gaps)
+}
+
+/// Keep the names matching the query, best matches first.
+pub fn rank_by_match<T>(query: &str, candidates: Vec<(T, String)>) -> Vec<(T, String)> {
+    let mut ranked: Vec<(usize, T, String)> = candidates
+        .into_iter()
+        .filter_map(|(it, name)| match_score(query, &name).map(|score| (score, it, name)))
+        .collect();
+    ranked.sort_by_key(|(score, _, name)| (*score, name.to_lowercase()));
+    ranked
+        .into_iter()
+        .take(SUGGESTIONS)
+        .map(|(_, it, name)| (it, name))
+        .collect()
+}
+
+/// Lowercase letters and digits of a name, so punctuation and spacing don't matter.
+fn simplify(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Whether two names likely mean the same game, like "Apex" and "apex legends".
+pub fn is_similar(a: &str, b: &str) -> bool {
+    let (a, b) = (simplify(a), simplify(b));
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    if short.is_empty() {
+        return false;
+    }
+    (short.chars().count() >= 4 && long.contains(&short))
+        || edit_distance(&short, &long) <= long.chars().count() / 4
+}
+
+/// Names similar to the query, closest first.
+pub fn similar_names<T>(
+    query: &str,
+    candidates: Vec<(T, String)>,
+    count: usize,
+) -> Vec<(T, String)> {
+    let query = simplify(query);
+    let mut similar: Vec<(usize, T, String)> = candidates
+        .into_iter()
+        .filter(|(_, name)| is_similar(&query, name))
+        .map(|(it, name)| (edit_distance(&query, &simplify(&name)), it, name))
+        .collect();
+    similar.sort_by_key(|(distance, _, name)| (*distance, name.to_lowercase()));
+    similar
+        .into_iter()
+        .take(count)
+        .map(|(_, it, name)| (it, name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fuzzy::*;
+
+    #[test]
+    pub fn test_match_score() {
+        assert_eq!(match_score("apex", "Apex"), Some(0));
+        assert_eq!(match_score("", "Apex"), Some(0));
+        assert_eq!(match_score("ap", "Apex Legends"), Some(1));
+        assert_eq!(match_score("leg", "Apex Legends"), Some(2));
+        assert_eq!(match_score("gend", "Apex Legends"), Some(3));
+        assert_eq!(match_score("axl", "Apex Legends"), Some(7));
+        assert_eq!(match_score("zz", "Apex Legends"), None);
+    }
+
+    #[test]
+    pub fn test_rank_by_match() {
+        let candidates = vec![
+            (1, "Minecraft".to_string()),
+            (2, "Apex Legends".to_string()),
+            (3, "Among Us".to_string()),
+            (4, "apex".to_string()),
+        ];
+        assert_eq!(
+            rank_by_match("apex", candidates.clone()),
+            vec![(4, "apex".to_string()), (2, "Apex Legends".to_string())]
+        );
+        assert_eq!(rank_by_match("", candidates).len(), 4);
+    }
+
+    #[test]
+    pub fn test_is_similar() {
+        assert!(is_similar("Apex", "apex legends"));
+        assert!(is_similar("Apex Legends", "apex-legends"));
+        assert!(is_similar("Minecraft", "mincraft"));
+        assert!(!is_similar("CS", "CS:GO"));
+        assert!(!is_similar("Minecraft", "Among Us"));
+        assert!(!is_similar("", "Among Us"));
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    pub fn test_similar_names() {
+        let candidates = vec![
+            (1, "Apex Legends".to_string()),
+            (2, "Minecraft".to_string()),
+            (3, "apex".to_string()),
+        ];
+        assert_eq!(
+            similar_names("Apex!", candidates.clone(), 4),
+            vec![(3, "apex".to_string()), (1, "Apex Legends".to_string())]
+        );
+        assert_eq!(similar_names("apex", candidates, 1).len(), 1);
+    }
+}
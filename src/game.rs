@@ -3,15 +3,53 @@ use std::time::Duration;
 use futures::{stream, StreamExt};
 use log::info;
 use poise::serenity_prelude::{
-    ButtonStyle, Color, MessageBuilder, ReactionType, Role, RoleId, User, UserId,
+    ButtonStyle, Guild, InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+    Mentionable, MessageBuilder, Permissions, Role, RoleId, User, UserId,
 };
 
 use crate::api;
+use crate::buttons::{join_button, schedule_expiry};
+use crate::categories::group_by_category;
+use crate::components::ComponentAction;
+use crate::fuzzy::{rank_by_match, similar_names};
+use crate::managed::check_game_role;
+use crate::pages::{
+    filter_entries, paginate, send_pages, sort_entries, ListEntry, SortOrder, DESCRIPTION_LIMIT,
+    PAGE_SIZE,
+};
+use crate::panels::refresh_panels;
+use crate::stats::{record, RoleEventKind};
 use crate::util::*;
 use crate::{Context, Error};
 
+/// Tell the author why a role can't be used, returning whether it can.
+pub async fn ensure_game_role(ctx: &Context<'_>, role: &Role) -> Result<bool, Error> {
+    let refusal = match check_game_role(ctx.data(), role) {
+        Ok(()) => return Ok(true),
+        Err(refusal) => refusal,
+    };
+    let message = MessageBuilder::new()
+        .role(role)
+        .push_line(" can't be used as a game role!")
+        .push_italic(refusal.to_string())
+        .build();
+    ctx.send(|f| f.embed(unsuccessful_interaction(|f| f.description(message))))
+        .await?;
+    Ok(false)
+}
+
+/// Roles offered at most instead of creating a new one.
+const SIMILAR_ROLES: usize = 4;
+/// Discord allows this many characters in a button label.
+const BUTTON_LABEL_LIMIT: usize = 80;
+/// Seconds to wait for a pick between similar roles.
+const PICK_TIMEOUT: u64 = 60;
+
 async fn join_role(ctx: &Context<'_>, role: &Role, content: Option<String>) -> Result<(), Error> {
-    let choice = ctx.data().0.lock().unwrap().add_user_to_role(
+    if !ensure_game_role(ctx, role).await? {
+        return Ok(());
+    }
+    let choice = ctx.data().roles.lock().unwrap().add_user_to_role(
         ctx.guild_id().unwrap(),
         role.id,
         ctx.author().id,
@@ -30,6 +68,13 @@ async fn join_role(ctx: &Context<'_>, role: &Role, content: Option<String>) -> R
             .await?;
         return Ok(());
     }
+    record(
+        ctx.data(),
+        ctx.guild_id().unwrap(),
+        role.id,
+        ctx.author().id,
+        RoleEventKind::Join,
+    );
 
     let message = MessageBuilder::new()
         .push("Added ")
@@ -47,16 +92,7 @@ async fn join_role(ctx: &Context<'_>, role: &Role, content: Option<String>) -> R
     let m = ctx
         .send(|f| {
             f.embed(successful_interaction(|f| f.description(content.clone())))
-                .components(|f| {
-                    f.create_action_row(|f| {
-                        f.create_button(|f| {
-                            f.custom_id(role.id)
-                                .emoji(ReactionType::from('🔔'))
-                                .style(ButtonStyle::Primary)
-                                .label("Join this role!")
-                        })
-                    })
-                })
+                .components(join_button(role.id.0, false))
         })
         .await?;
 
@@ -67,51 +103,127 @@ async fn join_role(ctx: &Context<'_>, role: &Role, content: Option<String>) -> R
         role.id
     );
 
-    save_to_db(ctx.data());
+    save_roles(ctx.data());
 
-    // wait 30 minutes
-    tokio::spawn(tokio::time::sleep(Duration::from_secs(60 * 30))).await?;
+    if let Some(m) = m {
+        let m = m.message().await?;
+        schedule_expiry(ctx.data(), m.channel_id, m.id, role.id.0, content);
+    }
+    Ok(())
+}
 
-    // turn off the button
-    'timeout: {
-        let m = match m {
-            Some(m) => m,
-            _ => break 'timeout,
-        };
-        let mut m = match m.message().await {
-            Ok(m) => m,
-            _ => break 'timeout,
-        };
+/// Suggest tracked roles that still exist, best matches first.
+fn role_choices(
+    ctx: Context<'_>,
+    roles: Vec<api::RoleId>,
+    partial: &str,
+) -> Vec<poise::AutocompleteChoice<String>> {
+    let roles = roles
+        .into_iter()
+        .filter_map(|id| {
+            RoleId::from(id)
+                .to_role_cached(ctx.discord())
+                .map(|role| (id, role.name))
+        })
+        .collect();
+    rank_by_match(partial, roles)
+        .into_iter()
+        .map(|(id, name)| poise::AutocompleteChoice {
+            name,
+            value: id.to_string(),
+        })
+        .collect()
+}
+
+/// Game roles of the guild, which can be joined even before anyone has.
+fn guild_role_ids(ctx: Context<'_>) -> Vec<api::RoleId> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Vec::new(),
+    };
+    ctx.data()
+        .managed
+        .lock()
+        .unwrap()
+        .show_managed_roles_of_guild(guild_id)
+}
 
-        m.edit(ctx.discord(), |f| {
-            f.embed(successful_interaction(|f| {
-                f.description(content.clone())
-                    .footer(|f| f.text("Button timed out! Do a new command."))
+/// Roles the author is subscribed to, which can be left.
+fn user_role_ids(ctx: Context<'_>) -> Vec<api::RoleId> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Vec::new(),
+    };
+    ctx.data()
+        .roles
+        .lock()
+        .unwrap()
+        .show_roles_of_user(guild_id, ctx.author().id)
+        .into_iter()
+        .copied()
+        .collect()
+}
+
+async fn autocomplete_guild_role(
+    ctx: Context<'_>,
+    partial: String,
+) -> Vec<poise::AutocompleteChoice<String>> {
+    role_choices(ctx, guild_role_ids(ctx), &partial)
+}
+
+async fn autocomplete_user_role(
+    ctx: Context<'_>,
+    partial: String,
+) -> Vec<poise::AutocompleteChoice<String>> {
+    role_choices(ctx, user_role_ids(ctx), &partial)
+}
+
+/// Find the role picked from the suggestions, or typed as a mention or name, among the candidates.
+async fn resolve_role(
+    ctx: Context<'_>,
+    input: &str,
+    candidates: &[api::RoleId],
+) -> Result<Option<Role>, Error> {
+    let guild = match ctx.guild() {
+        Some(g) => g,
+        None => return Ok(None),
+    };
+    let input = input.trim();
+    let id = input
+        .trim_start_matches("<@&")
+        .trim_end_matches('>')
+        .parse::<u64>()
+        .ok();
+    let role = guild
+        .roles
+        .values()
+        .filter(|role| candidates.contains(&role.id.0))
+        .find(|role| Some(role.id.0) == id || role.name.eq_ignore_ascii_case(input))
+        .cloned();
+
+    if role.is_none() {
+        ctx.send(|f| {
+            f.embed(unsuccessful_interaction(|f| {
+                f.description("Couldn't find that role! *Pick one of the suggestions.*")
             }))
-            .components(|f| {
-                f.create_action_row(|f| {
-                    f.create_button(|f| {
-                        f.custom_id(role.id)
-                            .emoji(ReactionType::from('🔔'))
-                            .style(ButtonStyle::Primary)
-                            .label("Join this role!")
-                            .disabled(true)
-                    })
-                })
-            })
         })
         .await?;
     }
-    Ok(())
+    Ok(role)
 }
 
 /// Join the notification list for a role
 #[poise::command(slash_command, category = "game")]
 pub async fn join(
     ctx: Context<'_>,
-    #[description = "Selected role"] role: Role,
+    #[description = "Selected role"]
+    #[autocomplete = "autocomplete_guild_role"]
+    role: String,
 ) -> Result<(), Error> {
-    join_role(&ctx, &role, None).await
+    match resolve_role(ctx, &role, &guild_role_ids(ctx)).await? {
+        Some(role) => join_role(&ctx, &role, None).await,
+        None => Ok(()),
+    }
 }
 
 /// Interact with game roles
@@ -120,6 +232,118 @@ pub async fn game(_ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
+/// Offer to join game roles with a similar name, returning whether to create the role anyway.
+async fn confirm_new_role(ctx: Context<'_>, guild: &Guild, game: &str) -> Result<bool, Error> {
+    let managed = ctx
+        .data()
+        .managed
+        .lock()
+        .unwrap()
+        .show_managed_roles_of_guild(guild.id);
+    let candidates = managed
+        .into_iter()
+        .filter_map(|id| {
+            guild
+                .roles
+                .get(&RoleId::from(id))
+                .map(|role| (id, role.name.clone()))
+        })
+        .collect();
+    let similar = similar_names(game, candidates, SIMILAR_ROLES);
+    if similar.is_empty() {
+        return Ok(true);
+    }
+
+    let mut message = MessageBuilder::new();
+    message.push("Did you mean ");
+    for (i, (id, _)) in similar.iter().enumerate() {
+        if i > 0 {
+            message.push(" or ");
+        }
+        message.role(RoleId::from(*id));
+    }
+    let message = message
+        .push_line("?")
+        .push_italic_safe(format!(
+            "Pick a role to join it, or create {} anyway.",
+            game
+        ))
+        .build();
+
+    let handle = ctx
+        .send(|f| {
+            f.embed(successful_interaction(|f| f.description(message)))
+                .components(|f| {
+                    f.create_action_row(|f| {
+                        for (id, name) in similar {
+                            f.create_button(|f| {
+                                f.custom_id(ComponentAction::Join(id).encode())
+                                    .style(ButtonStyle::Primary)
+                                    .label(
+                                        name.chars().take(BUTTON_LABEL_LIMIT).collect::<String>(),
+                                    )
+                            });
+                        }
+                        f.create_button(|f| {
+                            f.custom_id(ComponentAction::CreateRole.encode())
+                                .style(ButtonStyle::Secondary)
+                                .label("Create anyway")
+                        })
+                    })
+                })
+        })
+        .await?;
+    let handle = match handle {
+        Some(handle) => handle,
+        None => return Ok(false),
+    };
+    let m = handle.message().await?;
+
+    let picked = loop {
+        let interaction = match m
+            .await_component_interaction(ctx.discord())
+            .timeout(Duration::from_secs(PICK_TIMEOUT))
+            .await
+        {
+            Some(interaction) => interaction,
+            None => break None,
+        };
+        let create = ComponentAction::decode(&interaction.data.custom_id)
+            == Some(ComponentAction::CreateRole);
+        if interaction.user.id == ctx.author().id {
+            break Some((interaction, create));
+        }
+        // joins of other members are answered like any other join button
+        if create {
+            interaction
+                .create_interaction_response(ctx.discord(), |f| {
+                    f.kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|f| {
+                            f.content("❌ Only the author of the command can create the role!")
+                                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                        })
+                })
+                .await?;
+        }
+    };
+    match picked {
+        Some((interaction, true)) => {
+            interaction
+                .create_interaction_response(ctx.discord(), |f| {
+                    f.kind(InteractionResponseType::UpdateMessage)
+                        .interaction_response_data(|f| f.components(|f| f))
+                })
+                .await?;
+            Ok(true)
+        }
+        // picking a suggested role is answered like any other join button
+        _ => {
+            handle.edit(ctx, |f| f.components(|f| f)).await?;
+            Ok(false)
+        }
+    }
+}
+
 /// Join or create the notification list for a role
 #[poise::command(slash_command, category = "game")]
 pub async fn create(
@@ -131,20 +355,61 @@ pub async fn create(
         None => return Ok(()),
     };
 
+    // aliases set with `/game alias` are canonical names of existing roles
+    let canonical = ctx
+        .data()
+        .aliases
+        .lock()
+        .unwrap()
+        .role_of_alias(guild.id, &game);
+    let existing = canonical
+        .and_then(|id| guild.roles.get(&RoleId::from(id)))
+        .or_else(|| guild.role_by_name(&game))
+        .or_else(|| {
+            let name = game.trim().to_lowercase();
+            guild
+                .roles
+                .values()
+                .find(|role| role.name.to_lowercase() == name)
+        })
+        .cloned();
+
     let mut content = String::new();
+    let mut created = false;
 
-    let role = match guild.role_by_name(&game) {
-        Some(role) => Ok(role.clone()),
+    let role = match existing {
+        Some(role) => Ok(role),
         None => {
+            if !confirm_new_role(ctx, &guild, &game).await? {
+                return Ok(());
+            }
             content += format!("Created a new role {}!", game).as_str();
+            created = true;
             guild
-                .create_role(ctx.discord(), |f| f.name(&game).mentionable(true))
+                .create_role(ctx.discord(), |f| {
+                    f.name(&game)
+                        .mentionable(true)
+                        .permissions(Permissions::empty())
+                })
                 .await
         }
     };
 
+    if let (true, Ok(role)) = (created, &role) {
+        let _ = ctx
+            .data()
+            .managed
+            .lock()
+            .unwrap()
+            .add_managed_role(guild.id, role.id);
+        save_table("managed", &ctx.data().managed.lock().unwrap());
+    }
+
     if role.is_ok() {
         join_role(&ctx, &role.unwrap(), Some(content)).await?;
+        if created {
+            refresh_panels(ctx.discord(), ctx.data(), guild.id).await;
+        }
         return Ok(());
     }
 
@@ -162,16 +427,22 @@ pub async fn create(
 #[poise::command(slash_command, category = "game")]
 pub async fn leave(
     ctx: Context<'_>,
-    #[description = "Selected role"] mut role: Role,
+    #[description = "Selected role"]
+    #[autocomplete = "autocomplete_user_role"]
+    role: String,
 ) -> Result<(), Error> {
     let guild_id = match ctx.guild_id() {
         Some(id) => id,
         _ => return Ok(()),
     };
+    let mut role = match resolve_role(ctx, &role, &user_role_ids(ctx)).await? {
+        Some(role) => role,
+        None => return Ok(()),
+    };
 
     let choice =
         ctx.data()
-            .0
+            .roles
             .lock()
             .unwrap()
             .remove_user_from_role(guild_id, role.id, ctx.author().id);
@@ -192,6 +463,13 @@ pub async fn leave(
             .await?;
         return Ok(());
     }
+    record(
+        ctx.data(),
+        guild_id,
+        role.id,
+        ctx.author().id,
+        RoleEventKind::Leave,
+    );
 
     let members_empty = guild_id
         .members(ctx.discord(), None, None)
@@ -200,7 +478,7 @@ pub async fn leave(
         .find(|m| m.roles.contains(&role.id));
     let subscribers = ctx
         .data()
-        .0
+        .roles
         .lock()
         .unwrap()
         .show_users_of_role(guild_id, role.id)
@@ -239,7 +517,10 @@ pub async fn leave(
 
     info!("({}) {} left {}!", role.guild_id, ctx.author().id, role.id);
 
-    save_to_db(ctx.data());
+    save_roles(ctx.data());
+    if role_deleted {
+        refresh_panels(ctx.discord(), ctx.data(), guild_id).await;
+    }
     Ok(())
 }
 
@@ -247,11 +528,19 @@ pub async fn leave(
 #[poise::command(slash_command, category = "game", ephemeral = true)]
 pub async fn members(
     ctx: Context<'_>,
-    #[description = "Selected role"] role: Role,
+    #[description = "Selected role"]
+    #[autocomplete = "autocomplete_guild_role"]
+    role: String,
+    #[description = "How to sort the members"] sort: Option<SortOrder>,
+    #[description = "Only show members whose name contains this"] search: Option<String>,
 ) -> Result<(), Error> {
+    let role = match resolve_role(ctx, &role, &guild_role_ids(ctx)).await? {
+        Some(role) => role,
+        None => return Ok(()),
+    };
     let mut users: Vec<_> = ctx
         .data()
-        .0
+        .roles
         .lock()
         .unwrap()
         .show_users_of_role(role.guild_id, role.id)
@@ -260,6 +549,7 @@ pub async fn members(
         .map(UserId::from)
         .collect();
 
+    users.sort();
     users.dedup();
 
     let users: Vec<_> = stream::iter(users)
@@ -267,24 +557,41 @@ pub async fn members(
         .collect()
         .await;
 
-    let mb = users
+    let activity = ctx
+        .data()
+        .stats
+        .lock()
+        .unwrap()
+        .last_activity_of_users(role.guild_id, role.id);
+    let entries = users
         .into_iter()
-        .fold(&mut MessageBuilder::new(), |mb, u| {
-            mb.user(u);
-            mb
-        })
-        .build();
-
-    ctx.send(|f| {
-        f.embed(|f| {
-            f.title(format!("Users subscribed to {}:", role.name))
-                .color(Color::DARK_GREEN)
-                .description(mb)
+        .map(|u| {
+            let count = ctx
+                .data()
+                .roles
+                .lock()
+                .unwrap()
+                .show_roles_of_user(role.guild_id, u.id)
+                .len();
+            let active = activity.get(&u.id.0).copied();
+            ListEntry {
+                line: u.mention().to_string(),
+                name: u.name,
+                group: None,
+                count,
+                active,
+            }
         })
-    })
-    .await?;
-
-    Ok(())
+        .collect();
+    let mut entries = filter_entries(entries, search.as_deref());
+    sort_entries(&mut entries, sort.unwrap_or(SortOrder::Name));
+
+    send_pages(
+        ctx,
+        format!("Users subscribed to {}:", role.name),
+        paginate(&entries, PAGE_SIZE, DESCRIPTION_LIMIT),
+    )
+    .await
 }
 
 /// List the roles that a user will be notified for, or a guild if there is no user.
@@ -292,6 +599,8 @@ pub async fn members(
 pub async fn list(
     ctx: Context<'_>,
     #[description = "Selected user"] user: Option<User>,
+    #[description = "How to sort the roles"] sort: Option<SortOrder>,
+    #[description = "Only show roles whose name contains this"] search: Option<String>,
 ) -> Result<(), Error> {
     let guild_id = match ctx.guild_id() {
         Some(id) => id,
@@ -301,7 +610,7 @@ pub async fn list(
     let mut roles: Vec<api::RoleId> = user.as_ref().map_or_else(
         || {
             ctx.data()
-                .0
+                .roles
                 .lock()
                 .unwrap()
                 .show_roles_of_guild(guild_id)
@@ -311,7 +620,7 @@ pub async fn list(
         },
         |u| {
             ctx.data()
-                .0
+                .roles
                 .lock()
                 .unwrap()
                 .show_roles_of_user(guild_id, u.id)
@@ -326,29 +635,237 @@ pub async fn list(
 
     let roles = roles
         .into_iter()
-        .filter_map(|r| RoleId::from(r).to_role_cached(ctx.discord()));
+        .filter_map(|r| {
+            RoleId::from(r)
+                .to_role_cached(ctx.discord())
+                .map(|role| (r, role.name))
+        })
+        .collect();
+    let categories = ctx
+        .data()
+        .categories
+        .lock()
+        .unwrap()
+        .show_categories_of_guild(guild_id);
 
     let title = match &user {
         Some(user) => user.name.clone(),
         None => ctx.guild().unwrap().name,
     };
 
-    let mb = roles
-        .fold(&mut MessageBuilder::new(), |mb, u| {
-            mb.role(u);
-            mb
+    // categories only make sense when sorting by name
+    let order = sort.unwrap_or(SortOrder::Name);
+    let activity = ctx
+        .data()
+        .stats
+        .lock()
+        .unwrap()
+        .last_activity_of_roles(guild_id);
+    let entries = group_by_category(roles, &categories)
+        .into_iter()
+        .flat_map(|(category, roles)| {
+            roles
+                .into_iter()
+                .map(move |(id, name)| (category.clone(), id, name))
+        })
+        .map(|(category, id, name)| {
+            let count = ctx
+                .data()
+                .roles
+                .lock()
+                .unwrap()
+                .show_users_of_role(guild_id, id)
+                .len();
+            let active = activity.get(&id).copied();
+            ListEntry {
+                line: format!("{} · {} subscribers", RoleId::from(id).mention(), count),
+                name,
+                group: (order == SortOrder::Name).then(|| category),
+                count,
+                active,
+            }
+        })
+        .collect();
+    let mut entries = filter_entries(entries, search.as_deref());
+    sort_entries(&mut entries, order);
+
+    send_pages(
+        ctx,
+        format!("Roles of {}:", title),
+        paginate(&entries, PAGE_SIZE, DESCRIPTION_LIMIT),
+    )
+    .await
+}
+
+/// Move every subscriber of a role into another and delete it
+#[poise::command(
+    slash_command,
+    category = "game",
+    required_permissions = "MANAGE_ROLES"
+)]
+pub async fn merge(
+    ctx: Context<'_>,
+    #[description = "Role to merge and delete"] mut from: Role,
+    #[description = "Role to keep"] into: Role,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    if from.id == into.id {
+        ctx.send(|f| {
+            f.embed(unsuccessful_interaction(|f| {
+                f.description("Can't merge a role into itself!")
+            }))
+        })
+        .await?;
+        return Ok(());
+    }
+    if !ensure_game_role(&ctx, &from).await? || !ensure_game_role(&ctx, &into).await? {
+        return Ok(());
+    }
+
+    let merged = ctx
+        .data()
+        .roles
+        .lock()
+        .unwrap()
+        .merge_roles(guild_id, from.id, into.id);
+    let (moved, duplicates) = match merged {
+        Ok(counts) => counts,
+        // nobody was subscribed to it
+        Err(api::ApiError::Removal) => (0, 0),
+        Err(e) => return Err(e.into()),
+    };
+
+    {
+        // the old name keeps finding the role, like an alias set by hand
+        let mut aliases = ctx.data().aliases.lock().unwrap();
+        let old: Vec<String> = aliases
+            .show_aliases_of_role(guild_id, from.id)
+            .into_iter()
+            .cloned()
+            .collect();
+        for alias in old.iter().chain(std::iter::once(&from.name)) {
+            let _ = aliases.remove_alias(guild_id, from.id, alias);
+            let _ = aliases.add_alias(guild_id, into.id, alias);
+        }
+    }
+    // nothing may keep pointing at the deleted role
+    ctx.data()
+        .permissions
+        .lock()
+        .unwrap()
+        .move_role(guild_id, from.id, into.id);
+    ctx.data()
+        .voice
+        .lock()
+        .unwrap()
+        .move_role(guild_id, from.id, into.id);
+    ctx.data()
+        .sessions
+        .lock()
+        .unwrap()
+        .move_role(guild_id, from.id, into.id);
+    ctx.data()
+        .lfg
+        .lock()
+        .unwrap()
+        .move_role(guild_id, from.id, into.id);
+    let _ = ctx
+        .data()
+        .categories
+        .lock()
+        .unwrap()
+        .clear_category(guild_id, from.id);
+    let _ = ctx
+        .data()
+        .managed
+        .lock()
+        .unwrap()
+        .remove_managed_role(guild_id, from.id);
+    save_to_db(ctx.data());
+
+    let deleted = from.delete(ctx.discord()).await.is_ok();
+    info!(
+        "({}) {} merged {} into {}!",
+        guild_id,
+        ctx.author().id,
+        from.id,
+        into.id
+    );
+
+    let message = MessageBuilder::new()
+        .push(format!("✅ Moved {} subscribers from ", moved))
+        .push_bold_safe(&from.name)
+        .push(" into ")
+        .role(&into)
+        .push_line(format!(", {} already were subscribed!", duplicates))
+        .push(if deleted {
+            "💀 Role was deleted!"
+        } else {
+            "❌ Role can be deleted, but wasn't!"
         })
         .build();
+    ctx.send(|f| f.embed(successful_interaction(|f| f.description(message))))
+        .await?;
 
-    ctx.send(|f| {
-        f.embed(|f| {
-            f.title(format!("Roles of {}:", title))
-                .color(Color::DARK_GREEN)
-                .description(mb)
+    refresh_panels(ctx.discord(), ctx.data(), guild_id).await;
+    Ok(())
+}
+
+/// Rename a role while keeping its subscribers
+#[poise::command(
+    slash_command,
+    category = "game",
+    required_permissions = "MANAGE_ROLES"
+)]
+pub async fn rename(
+    ctx: Context<'_>,
+    #[description = "Selected role"] mut role: Role,
+    #[description = "New name of the role"] name: String,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    if !ensure_game_role(&ctx, &role).await? {
+        return Ok(());
+    }
+
+    let old = role.name.clone();
+    if role
+        .edit(ctx.discord(), |f| f.name(name.trim()))
+        .await
+        .is_err()
+    {
+        ctx.send(|f| {
+            f.embed(unsuccessful_interaction(|f| {
+                f.description("Failed to rename the role!")
+            }))
         })
-    })
-    .await?;
+        .await?;
+        return Ok(());
+    }
+
+    info!(
+        "({}) {} renamed {} from {}!",
+        guild_id,
+        ctx.author().id,
+        role.id,
+        old
+    );
+    let message = MessageBuilder::new()
+        .push("✅ Renamed ")
+        .push_bold_safe(old)
+        .push(" to ")
+        .role(&role)
+        .push("!")
+        .build();
+    ctx.send(|f| f.embed(successful_interaction(|f| f.description(message))))
+        .await?;
 
+    refresh_panels(ctx.discord(), ctx.data(), guild_id).await;
     Ok(())
 }
 
@@ -359,15 +876,25 @@ pub async fn invite(
     #[description = "Selected Role"] role: Role,
     #[description = "Selected Users"] users: Vec<User>,
 ) -> Result<(), Error> {
+    if !ensure_game_role(&ctx, &role).await? {
+        return Ok(());
+    }
     let mut choices = users.into_iter().map(|u| {
-        (
-            u.clone(),
-            ctx.data().0.lock().unwrap().add_user_to_role(
+        let choice = ctx.data().roles.lock().unwrap().add_user_to_role(
+            ctx.guild_id().unwrap(),
+            role.clone().id,
+            u.id,
+        );
+        if choice.is_ok() {
+            record(
+                ctx.data(),
                 ctx.guild_id().unwrap(),
-                role.clone().id,
+                role.id,
                 u.id,
-            ),
-        )
+                RoleEventKind::Join,
+            );
+        }
+        (u.clone(), choice)
     });
 
     if choices.any(|(_, r)| r.is_err()) {
@@ -400,16 +927,7 @@ pub async fn invite(
     let m = ctx
         .send(|f| {
             f.embed(successful_interaction(|f| f.description(message.clone())))
-                .components(|f| {
-                    f.create_action_row(|f| {
-                        f.create_button(|f| {
-                            f.custom_id(role.id)
-                                .emoji(ReactionType::from('🔔'))
-                                .style(ButtonStyle::Primary)
-                                .label("Join this role!")
-                        })
-                    })
-                })
+                .components(join_button(role.id.0, false))
         })
         .await?;
 
@@ -420,40 +938,11 @@ pub async fn invite(
         role.id
     );
 
-    save_to_db(ctx.data());
-
-    // wait 30 minutes
-    tokio::spawn(tokio::time::sleep(Duration::from_secs(60 * 30))).await?;
+    save_roles(ctx.data());
 
-    // turn off the button
-    'timeout: {
-        let m = match m {
-            Some(m) => m,
-            _ => break 'timeout,
-        };
-        let mut m = match m.message().await {
-            Ok(m) => m,
-            _ => break 'timeout,
-        };
-
-        m.edit(ctx.discord(), |f| {
-            f.embed(successful_interaction(|f| {
-                f.description(message.clone())
-                    .footer(|f| f.text("Button timed out! Do a new command."))
-            }))
-            .components(|f| {
-                f.create_action_row(|f| {
-                    f.create_button(|f| {
-                        f.custom_id(role.id)
-                            .emoji(ReactionType::from('🔔'))
-                            .style(ButtonStyle::Primary)
-                            .label("Join this role!")
-                            .disabled(true)
-                    })
-                })
-            })
-        })
-        .await?;
+    if let Some(m) = m {
+        let m = m.message().await?;
+        schedule_expiry(ctx.data(), m.channel_id, m.id, role.id.0, message);
     }
     Ok(())
 }
@@ -0,0 +1,59 @@
+use serde_derive::Serialize;
+
+/// What the probes of the HTTP server report about the bot.
+#[derive(Debug, Default, Clone)]
+pub struct Health {
+    /// Whether the gateway connection is currently up
+    pub connected: bool,
+    /// Whether every guild was loaded into the cache after starting
+    pub cache_ready: bool,
+    /// Unix timestamp of the last successful save of the roles database
+    pub last_save: Option<i64>,
+}
+
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub connected: bool,
+    pub cache_ready: bool,
+    pub last_save: Option<i64>,
+    pub rows: usize,
+}
+
+/// The bot is ready once it is connected to the gateway and has loaded its guilds.
+pub fn readiness(health: &Health, rows: usize) -> Readiness {
+    Readiness {
+        ready: health.connected && health.cache_ready,
+        connected: health.connected,
+        cache_ready: health.cache_ready,
+        last_save: health.last_save,
+        rows,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::health::*;
+
+    #[test]
+    pub fn test_readiness() {
+        let mut health = Health::default();
+        assert!(!readiness(&health, 0).ready);
+
+        health.connected = true;
+        assert!(!readiness(&health, 0).ready);
+
+        health.cache_ready = true;
+        health.last_save = Some(10);
+        assert_eq!(
+            readiness(&health, 3),
+            Readiness {
+                ready: true,
+                connected: true,
+                cache_ready: true,
+                last_save: Some(10),
+                rows: 3,
+            }
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{error, info};
+
+use crate::health::readiness;
+use crate::metrics;
+use crate::Data;
+
+async fn route(request: Request<Body>, user_data: Data) -> Result<Response<Body>, Infallible> {
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .body(Body::from(metrics::render())),
+        // answering at all means the process is alive
+        (&Method::GET, "/health") => Response::builder().body(Body::from("OK")),
+        (&Method::GET, "/ready") => {
+            let rows = user_data.roles.lock().unwrap().row_count();
+            let readiness = readiness(&user_data.health.lock().unwrap(), rows);
+            Response::builder()
+                .status(if readiness.ready {
+                    StatusCode::OK
+                } else {
+                    StatusCode::SERVICE_UNAVAILABLE
+                })
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    serde_json::to_string(&readiness).unwrap_or_default(),
+                ))
+        }
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+    Ok(response.unwrap_or_default())
+}
+
+/// Serve the operational endpoints until the bot exits.
+pub async fn run_http_server(addr: SocketAddr, user_data: Data) {
+    let service = make_service_fn(move |_| {
+        let user_data = user_data.clone();
+        let handle = move |request| route(request, user_data.clone());
+        async move { Ok::<_, Infallible>(service_fn(handle)) }
+    });
+    info!("Serving metrics and health checks on http://{}", addr);
+    if let Err(e) = Server::bind(&addr).serve(service).await {
+        error!("HTTP server stopped! {}", e);
+    }
+}
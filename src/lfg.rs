@@ -0,0 +1,561 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use log::{error, info};
+use poise::serenity_prelude::{
+    ButtonStyle, ChannelId, Color, Context as SerenityContext, CreateComponents, CreateEmbed,
+    GuildChannel, Interaction, InteractionApplicationCommandCallbackDataFlags,
+    InteractionResponseType, MessageBuilder, MessageId, ReactionType, Role, RoleId, UserId,
+};
+use serde_derive::{Deserialize, Serialize};
+use tqdb::{remove, search};
+
+use crate::api::{self, ApiError, GuildId, Snowflake, Table};
+use crate::components::ComponentAction;
+use crate::events::{add_thread_members, populate_thread};
+use crate::game::ensure_game_role;
+use crate::metrics::THREADS_CREATED;
+use crate::util::*;
+use crate::{Context, Data, Error};
+
+pub type QueueId = u64;
+
+/// A group of players looking for more people to play with.
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+pub struct LfgQueue {
+    pub id: QueueId,
+    pub guild_id: GuildId,
+    pub channel_id: Snowflake,
+    pub message_id: Snowflake,
+    pub role_id: api::RoleId,
+    pub voice_channel_id: Option<Snowflake>,
+    pub count: usize,
+    pub members: Vec<api::UserId>,
+    /// Unix timestamp after which the queue is closed
+    pub expires: i64,
+}
+
+impl LfgQueue {
+    pub fn is_full(&self) -> bool {
+        self.members.len() >= self.count
+    }
+}
+
+impl Table<LfgQueue> {
+    pub fn next_queue_id(&self) -> QueueId {
+        search!(&self.0 => |_: &LfgQueue| true)
+            .map(|it| it.id)
+            .max()
+            .unwrap_or(0)
+            + 1
+    }
+
+    pub fn add_queue(&mut self, queue: LfgQueue) -> Result<(), ApiError> {
+        self.0.insert_unique(queue).map_err(|_| ApiError::Insertion)
+    }
+
+    pub fn queue(&self, id: QueueId) -> Option<&LfgQueue> {
+        search!(&self.0 => move |it: &LfgQueue| it.id == id).next()
+    }
+
+    pub fn remove_queue(&mut self, id: QueueId) -> Result<LfgQueue, ApiError> {
+        remove!(&mut self.0 => move |it: &LfgQueue| it.id == id)
+            .next()
+            .ok_or(ApiError::Removal)
+    }
+
+    /// Add or remove a member of a queue, returning the queue after the change.
+    pub fn set_member<U: Into<api::UserId>>(
+        &mut self,
+        id: QueueId,
+        user_id: U,
+        joined: bool,
+    ) -> Result<LfgQueue, ApiError> {
+        let user_id = user_id.into();
+        let mut queue = self.remove_queue(id)?;
+        let present = queue.members.contains(&user_id);
+        let changed = match (joined, present) {
+            (true, false) if !queue.is_full() => {
+                queue.members.push(user_id);
+                true
+            }
+            (false, true) => {
+                queue.members.retain(|u| *u != user_id);
+                true
+            }
+            _ => false,
+        };
+        let res = queue.clone();
+        self.add_queue(queue)?;
+        if changed {
+            Ok(res)
+        } else if joined {
+            Err(ApiError::Insertion)
+        } else {
+            Err(ApiError::Removal)
+        }
+    }
+
+    /// Point the queues of a role at another role after a merge.
+    pub fn move_role<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &mut self,
+        guild_id: G,
+        from: R,
+        into: R,
+    ) -> usize {
+        let guild_id = guild_id.into();
+        let from = from.into();
+        let into = into.into();
+        let moved: Vec<LfgQueue> = remove!(&mut self.0 => move |it: &LfgQueue| it.guild_id == guild_id && it.role_id == from)
+            .collect();
+        for queue in moved.iter() {
+            let _ = self.add_queue(LfgQueue {
+                role_id: into,
+                ..queue.clone()
+            });
+        }
+        moved.len()
+    }
+
+    pub fn expired_queues(&self, now: i64) -> Vec<LfgQueue> {
+        search!(&self.0 => move |it: &LfgQueue| it.expires < now)
+            .cloned()
+            .collect()
+    }
+}
+
+fn queue_embed(
+    queue: &LfgQueue,
+    status: &str,
+) -> impl FnOnce(&mut CreateEmbed) -> &mut CreateEmbed {
+    let description = queue
+        .members
+        .iter()
+        .fold(
+            MessageBuilder::new()
+                .push("Looking for ")
+                .push(queue.count.to_string())
+                .push(" players for ")
+                .role(RoleId::from(queue.role_id))
+                .push_line("!"),
+            |mb, u| mb.push("• ").user(UserId::from(*u)).push_line(""),
+        )
+        .build();
+    let title = format!(
+        "🎮 Looking for group ({}/{})",
+        queue.members.len(),
+        queue.count
+    );
+    let expires = queue.expires;
+    let status = status.to_string();
+
+    move |f| {
+        f.title(title)
+            .color(Color::DARK_GREEN)
+            .description(description)
+            .field("Status", status, false)
+            .field("Closes", format!("<t:{}:R>", expires), false)
+    }
+}
+
+fn queue_buttons(
+    id: QueueId,
+    disabled: bool,
+) -> impl FnOnce(&mut CreateComponents) -> &mut CreateComponents {
+    move |f| {
+        f.create_action_row(|f| {
+            f.create_button(|f| {
+                f.custom_id(ComponentAction::LfgJoin(id).encode())
+                    .emoji(ReactionType::from('🙋'))
+                    .style(ButtonStyle::Primary)
+                    .label("Join group")
+                    .disabled(disabled)
+            })
+            .create_button(|f| {
+                f.custom_id(ComponentAction::LfgLeave(id).encode())
+                    .style(ButtonStyle::Secondary)
+                    .label("Leave group")
+                    .disabled(disabled)
+            })
+        })
+    }
+}
+
+/// Look for a group of players for a role
+#[poise::command(slash_command, category = "game")]
+pub async fn lfg(
+    ctx: Context<'_>,
+    #[description = "Selected role"] role: Role,
+    #[description = "Players needed, including you"] count: u32,
+    #[description = "Voice channel to meet in"] voice: Option<GuildChannel>,
+    #[description = "Minutes until the queue closes (default 60)"] minutes: Option<u32>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    if !ensure_game_role(&ctx, &role).await? {
+        return Ok(());
+    }
+
+    if count < 2 {
+        ctx.send(|f| {
+            f.embed(unsuccessful_interaction(|f| {
+                f.description("A group needs at least 2 players!")
+            }))
+        })
+        .await?;
+        return Ok(());
+    }
+
+    if !supports_private_threads(ctx.discord(), ctx.channel_id()).await {
+        ctx.send(|f| {
+            f.embed(unsuccessful_interaction(|f| {
+                f.description("Groups can only be looked for in text channels with threads!")
+            }))
+        })
+        .await?;
+        return Ok(());
+    }
+
+    let mut queue = LfgQueue {
+        id: ctx.data().lfg.lock().unwrap().next_queue_id(),
+        guild_id: guild_id.0,
+        channel_id: ctx.channel_id().0,
+        message_id: 0,
+        role_id: role.id.0,
+        voice_channel_id: voice.map(|c| c.id.0),
+        count: count as usize,
+        members: vec![ctx.author().id.0],
+        expires: Utc::now().timestamp() + minutes.unwrap_or(60) as i64 * 60,
+    };
+
+    let m = ctx
+        .send(|f| {
+            f.embed(queue_embed(&queue, "Open"))
+                .components(queue_buttons(queue.id, false))
+        })
+        .await?;
+
+    let m = match m {
+        Some(m) => m.message().await?,
+        None => return Ok(()),
+    };
+
+    queue.message_id = m.id.0;
+    info!(
+        "({}) {} is looking for a group of {} for {}!",
+        guild_id,
+        ctx.author().id,
+        count,
+        role.id
+    );
+    ctx.data().lfg.lock().unwrap().add_queue(queue.clone())?;
+    save_table("lfg", &ctx.data().lfg.lock().unwrap());
+
+    if let Err(e) = announce_queue(ctx.discord(), ctx.data(), &queue, &role).await {
+        error!("Failed to announce group {}! {}", queue.id, e);
+    }
+
+    Ok(())
+}
+
+/// Tell the subscribers of the role about a new queue, in a thread on its message.
+async fn announce_queue(
+    ctx: &SerenityContext,
+    user_data: &Data,
+    queue: &LfgQueue,
+    role: &Role,
+) -> Result<(), poise::serenity_prelude::SerenityError> {
+    let users: Vec<UserId> = user_data
+        .roles
+        .lock()
+        .unwrap()
+        .show_users_of_role(queue.guild_id, queue.role_id)
+        .into_iter()
+        .copied()
+        .filter(|u| !queue.members.contains(u))
+        .map(UserId::from)
+        .collect();
+    if users.is_empty() {
+        return Ok(());
+    }
+
+    let thread = ChannelId::from(queue.channel_id)
+        .create_public_thread(ctx, MessageId::from(queue.message_id), |f| {
+            f.name(format!("{} group search", role.name))
+                .auto_archive_duration(1440)
+                .kind(poise::serenity_prelude::model::channel::ChannelType::PublicThread)
+        })
+        .await?;
+    THREADS_CREATED.inc();
+
+    let style = user_data
+        .settings
+        .lock()
+        .unwrap()
+        .settings_of_guild(queue.guild_id)
+        .notification;
+    populate_thread(
+        ctx,
+        thread.id,
+        style,
+        &users,
+        &format!("a {} group", role.name),
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn interaction_lfg(
+    ctx: &SerenityContext,
+    user_data: &Data,
+    interaction: &Interaction,
+    id: QueueId,
+    joined: bool,
+) -> Result<(), poise::serenity_prelude::SerenityError> {
+    let m = match interaction.clone().message_component() {
+        Some(m) => m,
+        None => return Ok(()),
+    };
+
+    let queue = user_data.lfg.lock().unwrap().queue(id).cloned();
+    let queue = match queue {
+        Some(queue) => queue,
+        None => {
+            return m
+                .create_interaction_response(ctx, |f| {
+                    f.kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|f| {
+                            f.content("❌ This group is closed!")
+                                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                        })
+                })
+                .await
+        }
+    };
+    let unsubscribed = !user_data
+        .roles
+        .lock()
+        .unwrap()
+        .show_roles_of_user(queue.guild_id, m.user.id.0)
+        .contains(&&queue.role_id);
+    if joined && unsubscribed {
+        return m
+            .create_interaction_response(ctx, |f| {
+                f.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|f| {
+                        f.content(
+                            "❌ Only subscribers of this role can join, use `/game join` first!",
+                        )
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                    })
+            })
+            .await;
+    }
+
+    let choice = user_data
+        .lfg
+        .lock()
+        .unwrap()
+        .set_member(id, m.user.id.0, joined);
+
+    let queue = match choice {
+        Ok(queue) => queue,
+        Err(e) => {
+            let response = match e {
+                ApiError::Insertion => "❌ You're already in this group, or it is full!",
+                ApiError::Removal => "❌ You aren't in this group!",
+                _ => "❌ This group is closed!",
+            };
+            return m
+                .create_interaction_response(ctx, |f| {
+                    f.kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|f| {
+                            f.content(response)
+                                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                        })
+                })
+                .await;
+        }
+    };
+
+    info!(
+        "({}) {} {} group {}!",
+        queue.guild_id,
+        m.user.id,
+        if joined { "joined" } else { "left" },
+        id
+    );
+
+    let full = queue.is_full();
+    if full {
+        let _ = user_data.lfg.lock().unwrap().remove_queue(id);
+    }
+    save_table("lfg", &user_data.lfg.lock().unwrap());
+
+    m.create_interaction_response(ctx, |f| {
+        f.kind(InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|f| {
+                f.embed(queue_embed(&queue, if full { "Full!" } else { "Open" }))
+                    .components(queue_buttons(id, full))
+            })
+    })
+    .await?;
+
+    if full {
+        start_group(ctx, &queue).await?;
+    }
+    Ok(())
+}
+
+/// Whether private threads can be created in a channel, which only text channels allow.
+async fn supports_private_threads(ctx: &SerenityContext, channel_id: ChannelId) -> bool {
+    channel_id
+        .to_channel(ctx)
+        .await
+        .ok()
+        .and_then(|c| c.guild())
+        .map_or(false, |c| {
+            c.kind == poise::serenity_prelude::model::channel::ChannelType::Text
+        })
+}
+
+/// Put everyone in a full queue into a private thread together.
+///
+/// The group meets in the queue's channel when it can't have threads.
+async fn start_group(
+    ctx: &SerenityContext,
+    queue: &LfgQueue,
+) -> Result<(), poise::serenity_prelude::SerenityError> {
+    let role_name = RoleId::from(queue.role_id)
+        .to_role_cached(ctx)
+        .map(|r| r.name)
+        .unwrap_or_else(|| "Game".into());
+
+    let channel_id = ChannelId::from(queue.channel_id);
+    let members: Vec<UserId> = queue.members.iter().copied().map(UserId::from).collect();
+    let target = if supports_private_threads(ctx, channel_id).await {
+        let thread = channel_id
+            .create_private_thread(ctx, |f| {
+                f.name(format!("{} group", role_name))
+                    .auto_archive_duration(1440)
+            })
+            .await?;
+        THREADS_CREATED.inc();
+
+        let failed = add_thread_members(ctx, thread.id, members.iter().copied()).await;
+        if !failed.is_empty() {
+            error!(
+                "Failed to add {} users to group {}!",
+                failed.len(),
+                queue.id
+            );
+        }
+        thread.id
+    } else {
+        channel_id
+    };
+
+    let mut message = MessageBuilder::new();
+    for u in members.iter() {
+        message.user(*u).push(" ");
+    }
+    message.push_line("").push("Your group is ready!");
+    if let Some(voice) = queue.voice_channel_id {
+        message
+            .push(" Meet in ")
+            .channel(ChannelId::from(voice))
+            .push("!");
+    }
+    let message = message.build();
+
+    target
+        .send_message(ctx, |m| {
+            m.content(message)
+                .allowed_mentions(|f| f.users(members.iter().copied()))
+        })
+        .await?;
+
+    info!("({}) Group {} is full!", queue.guild_id, queue.id);
+    Ok(())
+}
+
+/// Close queues that didn't fill up in time, forever.
+pub async fn run_lfg_expiry(ctx: SerenityContext, user_data: Data) {
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+    loop {
+        interval.tick().await;
+        let expired = user_data
+            .lfg
+            .lock()
+            .unwrap()
+            .expired_queues(Utc::now().timestamp());
+
+        for queue in expired {
+            let _ = user_data.lfg.lock().unwrap().remove_queue(queue.id);
+            let edit = ChannelId::from(queue.channel_id)
+                .edit_message(&ctx, MessageId::from(queue.message_id), |f| {
+                    f.embed(queue_embed(&queue, "Closed, not enough players joined."))
+                        .components(queue_buttons(queue.id, true))
+                })
+                .await;
+            if let Err(e) = edit {
+                error!("Failed to close group {}! {}", queue.id, e);
+            }
+            info!("({}) Group {} expired!", queue.guild_id, queue.id);
+            save_table("lfg", &user_data.lfg.lock().unwrap());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lfg::*;
+    use tqdb::Database;
+
+    fn queue(count: usize, members: Vec<api::UserId>) -> LfgQueue {
+        LfgQueue {
+            id: 1,
+            guild_id: 1,
+            channel_id: 1,
+            message_id: 1,
+            role_id: 1,
+            voice_channel_id: None,
+            count,
+            members,
+            expires: 0,
+        }
+    }
+
+    #[test]
+    pub fn test_is_full() {
+        assert!(!queue(2, vec![1]).is_full());
+        assert!(queue(2, vec![1, 2]).is_full());
+    }
+
+    #[test]
+    pub fn test_set_member() {
+        let mut table = Table(Database::from(vec![queue(3, vec![1])]));
+        assert_eq!(table.set_member(1, 2u64, true).unwrap().members, vec![1, 2]);
+        assert!(matches!(
+            table.set_member(1, 2u64, true),
+            Err(ApiError::Insertion)
+        ));
+        assert_eq!(table.set_member(1, 2u64, false).unwrap().members, vec![1]);
+        assert!(matches!(
+            table.set_member(1, 2u64, false),
+            Err(ApiError::Removal)
+        ));
+
+        assert!(table.set_member(1, 2u64, true).is_ok());
+        assert!(table.set_member(1, 3u64, true).unwrap().is_full());
+        assert!(matches!(
+            table.set_member(1, 4u64, true),
+            Err(ApiError::Insertion)
+        ));
+        assert_eq!(table.queue(1).unwrap().members, vec![1, 2, 3]);
+        assert!(table.set_member(2, 4u64, true).is_err());
+    }
+}
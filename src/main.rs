@@ -1,26 +1,95 @@
 #![warn(rustdoc::all)]
 #![feature(async_closure)]
-#![feature(label_break_value)]
 #![feature(drain_filter)]
 
 use dotenv as env;
 use log::LevelFilter;
-use poise::{serenity_prelude::Color, FrameworkOptions, PrefixFrameworkOptions};
+use poise::{
+    serenity_prelude::{ChannelId, Color, GatewayIntents, MessageId},
+    FrameworkOptions, PrefixFrameworkOptions,
+};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
-use crate::api::RolesDatabase;
+use crate::aliases::*;
+use crate::api::{RolesDatabase, Table};
+use crate::buttons::*;
+use crate::categories::*;
+use crate::channels::*;
 use crate::deals::*;
 use crate::events::*;
 use crate::game::*;
+use crate::health::Health;
+use crate::http::run_http_server;
+use crate::lfg::*;
+use crate::managed::*;
+use crate::panels::*;
+use crate::permissions::*;
+use crate::sessions::*;
+use crate::settings::*;
+use crate::stats::*;
+use crate::suggestions::*;
+use crate::util::table_path;
+use crate::voice::*;
 
+mod aliases;
 mod api;
+mod buttons;
+mod categories;
+mod channels;
+mod components;
 mod deals;
 mod events;
+mod fuzzy;
 mod game;
+mod health;
+mod http;
+mod lfg;
+mod managed;
+mod metrics;
+mod pages;
+mod panels;
+mod permissions;
+mod sessions;
+mod settings;
+mod stats;
+mod suggestions;
+mod template;
 mod util;
+mod voice;
 
-#[derive(Debug)]
-pub struct Data(std::sync::Mutex<RolesDatabase>);
+type Shared<T> = std::sync::Arc<std::sync::Mutex<T>>;
+
+fn shared<T>(value: T) -> Shared<T> {
+    std::sync::Arc::new(std::sync::Mutex::from(value))
+}
+
+/// State shared by commands, events and background tasks.
+#[derive(Debug, Clone)]
+pub struct Data {
+    pub roles: Shared<RolesDatabase>,
+    pub permissions: Shared<Table<PingPermission>>,
+    pub settings: Shared<Table<GuildSettings>>,
+    pub aliases: Shared<Table<RoleAlias>>,
+    pub channels: Shared<Table<ChannelRule>>,
+    pub sessions: Shared<Table<Session>>,
+    pub rsvps: Shared<Table<Rsvp>>,
+    pub lfg: Shared<Table<LfgQueue>>,
+    pub voice: Shared<Table<VoiceLink>>,
+    pub suggestions: Shared<Table<Suggestion>>,
+    pub suggestion_opt_outs: Shared<Table<SuggestionOptOut>>,
+    pub buttons: Shared<Table<ExpiringButton>>,
+    pub panels: Shared<Table<Panel>>,
+    pub categories: Shared<Table<RoleCategory>>,
+    pub managed: Shared<Table<ManagedRole>>,
+    pub stats: Shared<Table<RoleEvent>>,
+    pub notified: Shared<HashMap<MessageId, NotifiedMessage>>,
+    /// When each voice channel last notified its role
+    pub voice_cooldowns: Shared<HashMap<ChannelId, i64>>,
+    pub health: Shared<Health>,
+    /// Unix timestamp of when the bot started
+    pub started: i64,
+}
 type Error = Box<dyn std::error::Error + Send + Sync>;
 type Context<'a> = poise::Context<'a, Data, Error>;
 
@@ -45,14 +114,33 @@ pub async fn help(
             .description("Allows for various hidden roles.")
             .footer(|f| f
                 .text("<> denotes required arguments, [] denotes optional arguments"))
-            .field("/help", "Show this menu", false)
-            .field("/deals <search string>", "Fetch game deals from isthereanydeal.com for a game", false)
-            .field("/game create <role name>", "Join or create the notification list for a role", false)
-            .field("/game leave <@role>", "Leave the notification list for a role", false)
-            .field("/game list [@user]", "List the role that a user will be notified for, or a guild if there is no user", false)
-            .field("/game join <@role>", "Join the notification list for a role", false)
-            .field("/game members <@role>", "Display the members of a role", false)
-            .field("$game invite <@role> (<@users> ..)", "Makes specified users join a role. Sends a button to them to opt out.", false))
+            .field("General", "\
+                `/help` Show this menu\n\
+                `/deals <search string>` Fetch game deals from isthereanydeal.com for a game", false)
+            .field("Roles", "\
+                `/game create <role name>` Join or create the notification list for a role\n\
+                `/game join <@role>` Join the notification list for a role\n\
+                `/game leave <@role>` Leave the notification list for a role\n\
+                `/game list [@user] [sort] [search]` List the roles a user will be notified for, or a guild if there is no user\n\
+                `/game members <@role> [sort] [search]` Display the members of a role\n\
+                `$game invite <@role> (<@users> ..)` Makes specified users join a role. Sends a button to them to opt out.", false)
+            .field("Playing together", "\
+                `/game schedule <@role> <time> [note] [reminder minutes]` Schedule a session that subscribers can RSVP to\n\
+                `/game lfg <@role> <count> [#voice] [minutes]` Look for a group of players for a role\n\
+                `/game suggestions <enabled>` Turn suggestions to join roles for games you play on or off\n\
+                `/game stats [@role]` Show statistics about the roles of this guild", false)
+            .field("Admin", "\
+                `/game permissions <@role> [action] [@user] [@role]` Show or change who may ping a role\n\
+                `/game settings [thread name] [timezone] [notification] [trigger] [max position]` Show or change the settings of this guild\n\
+                `/game alias <@role> [action] [alias]` Show or change the other names of a role, also used by `/game create`\n\
+                `/game channel [#channel] [action] [#channel]` Show or change where role pings create threads\n\
+                `/game voice [#voice] [@role] [#channel] [threshold] [cooldown]` Show or change which voice channels notify a role", false)
+            .field("Role admin", "\
+                `/game panel [category]` Post a panel for members to pick their roles, replacing the previous one of the category\n\
+                `/game category [action] [@role] [name] [new name]` Show or change the categories roles are listed under\n\
+                `/game managed [action] [@role]` Show or change which roles count as game roles\n\
+                `/game merge <@from> <@into>` Move every subscriber of a role into another and delete it\n\
+                `/game rename <@role> <name>` Rename a role while keeping its subscribers", false))
     ).await?;
     Ok(())
 }
@@ -83,12 +171,77 @@ async fn main() {
         .expect("Expected BOT_ROLES_DB to be set in environment.")
         .into();
     let db = RolesDatabase::try_from(db_file.as_path()).unwrap_or_default();
+    let permissions = Table::try_from(table_path("permissions").as_path()).unwrap_or_default();
+    let settings = Table::try_from(table_path("settings").as_path()).unwrap_or_default();
+    let aliases = Table::try_from(table_path("aliases").as_path()).unwrap_or_default();
+    let channels = Table::try_from(table_path("channels").as_path()).unwrap_or_default();
+    let sessions = Table::try_from(table_path("sessions").as_path()).unwrap_or_default();
+    let rsvps = Table::try_from(table_path("rsvps").as_path()).unwrap_or_default();
+    let lfg = Table::try_from(table_path("lfg").as_path()).unwrap_or_default();
+    let voice = Table::try_from(table_path("voice").as_path()).unwrap_or_default();
+    let suggestions = Table::try_from(table_path("suggestions").as_path()).unwrap_or_default();
+    let suggestion_opt_outs =
+        Table::try_from(table_path("suggestion_opt_outs").as_path()).unwrap_or_default();
+    let stats = Table::try_from(table_path("stats").as_path()).unwrap_or_default();
+    let buttons = Table::try_from(table_path("buttons").as_path()).unwrap_or_default();
+    let panels = Table::try_from(table_path("panels").as_path()).unwrap_or_default();
+    let categories = Table::try_from(table_path("categories").as_path()).unwrap_or_default();
+    // tracked roles are adopted on the first start, a registry that fails to load is never replaced
+    let managed_path = table_path("managed");
+    let managed = if managed_path.exists() {
+        Table::try_from(managed_path.as_path()).expect("Error loading the managed roles table.")
+    } else {
+        adopt_tracked_roles(&db)
+    };
+    let data = Data {
+        roles: shared(db),
+        permissions: shared(permissions),
+        settings: shared(settings),
+        aliases: shared(aliases),
+        channels: shared(channels),
+        sessions: shared(sessions),
+        rsvps: shared(rsvps),
+        lfg: shared(lfg),
+        voice: shared(voice),
+        suggestions: shared(suggestions),
+        suggestion_opt_outs: shared(suggestion_opt_outs),
+        stats: shared(stats),
+        buttons: shared(buttons),
+        panels: shared(panels),
+        categories: shared(categories),
+        managed: shared(managed),
+        notified: Default::default(),
+        voice_cooldowns: Default::default(),
+        health: Default::default(),
+        started: chrono::Utc::now().timestamp(),
+    };
     let options = FrameworkOptions {
         commands: vec![
             help(),
             register(),
             poise::Command {
-                subcommands: vec![join(), create(), members(), list(), leave(), invite()],
+                subcommands: vec![
+                    join(),
+                    create(),
+                    members(),
+                    list(),
+                    leave(),
+                    invite(),
+                    permissions(),
+                    settings(),
+                    alias(),
+                    channel(),
+                    schedule(),
+                    lfg(),
+                    voice(),
+                    suggestions(),
+                    stats(),
+                    panel(),
+                    category(),
+                    managed(),
+                    merge(),
+                    rename(),
+                ],
                 ..game()
             },
             deals(),
@@ -99,19 +252,42 @@ async fn main() {
             ..Default::default()
         },
         on_error: |error| Box::pin(on_error(error)),
+        pre_command: |ctx| {
+            Box::pin(async move {
+                metrics::COMMANDS
+                    .with_label_values(&[ctx.command().name])
+                    .inc();
+            })
+        },
         listener: |ctx, event, framework, user_data| {
             Box::pin(event_listener(ctx, event, framework, user_data))
         },
         command_check: Some(|ctx| Box::pin(command_check(ctx))),
         ..Default::default()
     };
+    if let Ok(addr) = env::var("BOT_HTTP_ADDR") {
+        let addr = addr
+            .parse()
+            .expect("Expected BOT_HTTP_ADDR to be an address like 127.0.0.1:9100.");
+        tokio::spawn(run_http_server(addr, data.clone()));
+    }
+
+    // suggesting roles needs the privileged presence intent
+    let mut intents = GatewayIntents::non_privileged();
+    if env::var("BOT_PRESENCE_SUGGESTIONS").map_or(false, |it| it == "true") {
+        intents |= GatewayIntents::GUILD_PRESENCES;
+    }
+
     poise::Framework::build()
         .token(env::var("BOT_TOKEN").expect("Expected BOT_TOKEN to be set in environment."))
-        .user_data_setup(move |_ctx, _ready, _framework| {
+        .client_settings(move |c| c.intents(intents))
+        .user_data_setup(move |ctx, _ready, _framework| {
             Box::pin(async move {
-                Ok(Data {
-                    0: std::sync::Mutex::from(db),
-                })
+                tokio::spawn(run_session_reminders(ctx.clone(), data.clone()));
+                tokio::spawn(run_lfg_expiry(ctx.clone(), data.clone()));
+                tokio::spawn(run_button_expiry(ctx.clone(), data.clone()));
+                tokio::spawn(run_stats_saves(data.clone()));
+                Ok(data)
             })
         })
         .options(options)
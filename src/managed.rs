@@ -0,0 +1,306 @@
+use log::info;
+use poise::serenity_prelude::{
+    Context as SerenityContext, MessageBuilder, Permissions, Role, RoleId,
+};
+use serde_derive::{Deserialize, Serialize};
+use thiserror::Error;
+use tqdb::{remove, search};
+
+use crate::api::{self, ApiError, GuildId, RolesDatabase, Table};
+use crate::util::*;
+use crate::{Context, Data, Error};
+
+/// A role that counts as a game role, because the bot created it or an admin adopted it.
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+pub struct ManagedRole {
+    guild_id: GuildId,
+    role_id: api::RoleId,
+}
+
+impl Table<ManagedRole> {
+    pub fn add_managed_role<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &mut self,
+        guild_id: G,
+        role_id: R,
+    ) -> Result<(), ApiError> {
+        self.0
+            .insert_unique(ManagedRole {
+                guild_id: guild_id.into(),
+                role_id: role_id.into(),
+            })
+            .map_err(|_| ApiError::Insertion)
+    }
+
+    pub fn remove_managed_role<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &mut self,
+        guild_id: G,
+        role_id: R,
+    ) -> Result<ManagedRole, ApiError> {
+        let guild_id = guild_id.into();
+        let role_id = role_id.into();
+        remove!(&mut self.0 => move |it: &ManagedRole| it.guild_id == guild_id && it.role_id == role_id)
+            .next()
+            .ok_or(ApiError::Removal)
+    }
+
+    pub fn is_managed<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &self,
+        guild_id: G,
+        role_id: R,
+    ) -> bool {
+        let guild_id = guild_id.into();
+        let role_id = role_id.into();
+        search!(&self.0 => move |it: &ManagedRole| it.guild_id == guild_id && it.role_id == role_id)
+            .next()
+            .is_some()
+    }
+
+    pub fn show_managed_roles_of_guild<G: Into<GuildId>>(&self, guild_id: G) -> Vec<api::RoleId> {
+        let guild_id = guild_id.into();
+        search!(&self.0 => move |it: &ManagedRole| it.guild_id == guild_id)
+            .map(|it| it.role_id)
+            .collect()
+    }
+}
+
+/// Registry for guilds that tracked roles before it existed, adopting every tracked role.
+pub fn adopt_tracked_roles(roles: &RolesDatabase) -> Table<ManagedRole> {
+    let mut table = Table::default();
+    for (guild_id, role_id) in roles.show_tracked_roles() {
+        let _ = table.add_managed_role(guild_id, role_id);
+    }
+    table
+}
+
+/// Why a role can't be used as a game role.
+#[derive(Error, Debug, Eq, PartialEq)]
+pub enum RoleRefusal {
+    #[error("It grants permissions, or is managed by Discord or an integration.")]
+    Privileged,
+    #[error("It is above the highest position allowed for game roles.")]
+    TooHigh,
+    #[error("It isn't a game role. An admin can adopt it with `/game managed`.")]
+    Unmanaged,
+    #[error("It doesn't exist anymore.")]
+    Missing,
+}
+
+/// Permissions that make a role staff rather than a game.
+fn elevated_permissions() -> Permissions {
+    Permissions::ADMINISTRATOR
+        | Permissions::MANAGE_GUILD
+        | Permissions::MANAGE_ROLES
+        | Permissions::MANAGE_CHANNELS
+        | Permissions::MANAGE_MESSAGES
+        | Permissions::MANAGE_WEBHOOKS
+        | Permissions::MANAGE_NICKNAMES
+        | Permissions::MANAGE_EMOJIS_AND_STICKERS
+        | Permissions::KICK_MEMBERS
+        | Permissions::BAN_MEMBERS
+        | Permissions::MENTION_EVERYONE
+        | Permissions::MUTE_MEMBERS
+        | Permissions::DEAFEN_MEMBERS
+        | Permissions::MOVE_MEMBERS
+        | Permissions::VIEW_AUDIT_LOG
+}
+
+/// Whether a role is `@everyone`, managed by an integration or grants staff permissions.
+pub fn is_privileged(permissions: Permissions, everyone: bool, integration: bool) -> bool {
+    everyone || integration || permissions.intersects(elevated_permissions())
+}
+
+pub fn check_role(
+    privileged: bool,
+    position: i64,
+    managed: bool,
+    max_position: Option<i64>,
+) -> Result<(), RoleRefusal> {
+    if privileged {
+        return Err(RoleRefusal::Privileged);
+    }
+    if max_position.map_or(false, |max| position > max) {
+        return Err(RoleRefusal::TooHigh);
+    }
+    if !managed {
+        return Err(RoleRefusal::Unmanaged);
+    }
+    Ok(())
+}
+
+fn role_is_privileged(role: &Role) -> bool {
+    is_privileged(role.permissions, role.id.0 == role.guild_id.0, role.managed)
+}
+
+/// Check that a role may be joined as a game role.
+pub fn check_game_role(user_data: &Data, role: &Role) -> Result<(), RoleRefusal> {
+    let max_position = user_data
+        .settings
+        .lock()
+        .unwrap()
+        .settings_of_guild(role.guild_id.0)
+        .max_role_position;
+    let managed = user_data
+        .managed
+        .lock()
+        .unwrap()
+        .is_managed(role.guild_id.0, role.id.0);
+    check_role(
+        role_is_privileged(role),
+        role.position,
+        managed,
+        max_position,
+    )
+}
+
+/// Check that a role picked from a button or menu may be joined as a game role.
+pub fn check_game_role_id<R: Into<api::RoleId>>(
+    ctx: &SerenityContext,
+    user_data: &Data,
+    role_id: R,
+) -> Result<(), RoleRefusal> {
+    match RoleId::from(role_id.into()).to_role_cached(ctx) {
+        Some(role) => check_game_role(user_data, &role),
+        None => Err(RoleRefusal::Missing),
+    }
+}
+
+#[derive(poise::SlashChoiceParameter)]
+pub enum ManagedAction {
+    #[name = "Adopt the role as a game role"]
+    Adopt,
+    #[name = "Stop treating the role as a game role"]
+    Release,
+}
+
+/// Show or change which roles count as game roles
+#[poise::command(
+    slash_command,
+    category = "game",
+    required_permissions = "MANAGE_ROLES"
+)]
+pub async fn managed(
+    ctx: Context<'_>,
+    #[description = "Change to make"] action: Option<ManagedAction>,
+    #[description = "Selected role"] role: Option<Role>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    if let (Some(action), Some(role)) = (action, &role) {
+        let result = match action {
+            ManagedAction::Adopt if role_is_privileged(role) => {
+                Err(RoleRefusal::Privileged.to_string())
+            }
+            ManagedAction::Adopt => ctx
+                .data()
+                .managed
+                .lock()
+                .unwrap()
+                .add_managed_role(guild_id, role.id)
+                .map_err(|_| "It already is a game role.".to_string()),
+            ManagedAction::Release => ctx
+                .data()
+                .managed
+                .lock()
+                .unwrap()
+                .remove_managed_role(guild_id, role.id)
+                .map(drop)
+                .map_err(|_| "It isn't a game role.".to_string()),
+        };
+
+        if let Err(reason) = result {
+            let message = MessageBuilder::new()
+                .push("Failed to change ")
+                .role(role)
+                .push_line("!")
+                .push_italic(reason)
+                .build();
+            ctx.send(|f| f.embed(unsuccessful_interaction(|f| f.description(message))))
+                .await?;
+            return Ok(());
+        }
+
+        info!(
+            "({}) {} changed whether {} is a game role!",
+            guild_id,
+            ctx.author().id,
+            role.id
+        );
+        save_table("managed", &ctx.data().managed.lock().unwrap());
+    }
+
+    let roles = ctx
+        .data()
+        .managed
+        .lock()
+        .unwrap()
+        .show_managed_roles_of_guild(guild_id);
+    let mut message = MessageBuilder::new();
+    if roles.is_empty() {
+        message.push_italic("No game roles yet!");
+    }
+    for id in roles {
+        message.push("• ").role(RoleId::from(id)).push_line("");
+    }
+    let message = message.build();
+
+    ctx.send(|f| {
+        f.embed(successful_interaction(|f| {
+            f.title("Game roles").description(message)
+        }))
+    })
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::managed::*;
+    use tqdb::Database;
+
+    #[test]
+    pub fn test_is_privileged() {
+        let member = Permissions::SEND_MESSAGES | Permissions::VIEW_CHANNEL;
+        assert!(!is_privileged(member, false, false));
+        assert!(is_privileged(
+            member | Permissions::KICK_MEMBERS,
+            false,
+            false
+        ));
+        assert!(is_privileged(Permissions::empty(), true, false));
+        assert!(is_privileged(Permissions::empty(), false, true));
+    }
+
+    #[test]
+    pub fn test_check_role() {
+        assert_eq!(check_role(false, 5, true, None), Ok(()));
+        assert_eq!(check_role(false, 5, true, Some(5)), Ok(()));
+        assert_eq!(
+            check_role(false, 6, true, Some(5)),
+            Err(RoleRefusal::TooHigh)
+        );
+        assert_eq!(
+            check_role(false, 5, false, None),
+            Err(RoleRefusal::Unmanaged)
+        );
+        assert_eq!(
+            check_role(true, 5, true, None),
+            Err(RoleRefusal::Privileged)
+        );
+    }
+
+    #[test]
+    pub fn test_managed_roles() {
+        let mut table = Table(Database::from(Vec::new()));
+        assert!(table.add_managed_role(1u64, 2u64).is_ok());
+        assert!(table.add_managed_role(1u64, 2u64).is_err());
+        assert!(table.is_managed(1u64, 2u64));
+        assert!(!table.is_managed(2u64, 2u64));
+        assert_eq!(table.show_managed_roles_of_guild(1u64), vec![2]);
+        assert!(table.remove_managed_role(1u64, 2u64).is_ok());
+        assert!(!table.is_managed(1u64, 2u64));
+    }
+}
@@ -0,0 +1,85 @@
+use lazy_static::lazy_static;
+use log::error;
+use prometheus::{
+    register_histogram, register_int_counter, register_int_counter_vec, register_int_gauge_vec,
+    Encoder, Histogram, IntCounter, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+
+lazy_static! {
+    pub static ref COMMANDS: IntCounterVec = register_int_counter_vec!(
+        "indexbot_commands_total",
+        "Commands invoked, by name",
+        &["command"]
+    )
+    .unwrap();
+    pub static ref ERRORS: IntCounterVec = register_int_counter_vec!(
+        "indexbot_errors_total",
+        "Framework errors, by kind",
+        &["kind"]
+    )
+    .unwrap();
+    pub static ref THREADS_CREATED: IntCounter =
+        register_int_counter!("indexbot_threads_created_total", "Threads created").unwrap();
+    pub static ref THREAD_MEMBERS_ADDED: IntCounter = register_int_counter!(
+        "indexbot_thread_members_added_total",
+        "Users added to threads"
+    )
+    .unwrap();
+    pub static ref ITAD_LATENCY: Histogram = register_histogram!(
+        "indexbot_itad_request_seconds",
+        "Latency of requests to isthereanydeal.com"
+    )
+    .unwrap();
+    pub static ref SAVE_DURATION: Histogram = register_histogram!(
+        "indexbot_db_save_seconds",
+        "Time spent saving the databases"
+    )
+    .unwrap();
+    pub static ref DB_ROWS: IntGaugeVec = register_int_gauge_vec!(
+        "indexbot_db_rows",
+        "Rows in each database table, as of the last save",
+        &["table"]
+    )
+    .unwrap();
+}
+
+/// Label of a framework error, naming its kind.
+pub fn variant_name<U, E>(error: &poise::FrameworkError<'_, U, E>) -> &'static str {
+    use poise::FrameworkError::*;
+    match error {
+        Setup { .. } => "Setup",
+        Listener { .. } => "Listener",
+        Command { .. } => "Command",
+        ArgumentParse { .. } => "ArgumentParse",
+        CommandStructureMismatch { .. } => "CommandStructureMismatch",
+        CooldownHit { .. } => "CooldownHit",
+        MissingBotPermissions { .. } => "MissingBotPermissions",
+        MissingUserPermissions { .. } => "MissingUserPermissions",
+        NotAnOwner { .. } => "NotAnOwner",
+        GuildOnly { .. } => "GuildOnly",
+        CommandCheckFailed { .. } => "CommandCheckFailed",
+        _ => "Other",
+    }
+}
+
+/// Every registered metric in the Prometheus text format.
+pub fn render() -> String {
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        error!("Failed to encode metrics! {}", e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::*;
+
+    #[test]
+    pub fn test_variant_name() {
+        let error = poise::FrameworkError::<(), String>::Setup {
+            error: "no token".into(),
+        };
+        assert_eq!(variant_name(&error), "Setup");
+    }
+}
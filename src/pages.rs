@@ -0,0 +1,271 @@
+use std::time::Duration;
+
+use poise::serenity_prelude::{
+    ButtonStyle, Color, CreateComponents, CreateEmbed,
+    InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+};
+
+use crate::categories::UNCATEGORIZED;
+use crate::components::ComponentAction;
+use crate::{Context, Error};
+
+/// Entries shown on a single page.
+pub const PAGE_SIZE: usize = 20;
+/// Discord allows this many characters in an embed description.
+pub const DESCRIPTION_LIMIT: usize = 4096;
+/// Seconds the page buttons keep working after the last use.
+const PAGE_TIMEOUT: u64 = 5 * 60;
+
+/// How to sort a list, members are sorted by how many roles they are subscribed to.
+#[derive(poise::SlashChoiceParameter, Debug, Eq, PartialEq, Clone, Copy)]
+pub enum SortOrder {
+    #[name = "Name"]
+    Name,
+    #[name = "Subscriber count"]
+    Subscribers,
+    #[name = "Recently active"]
+    Activity,
+}
+
+/// A role or member in a paginated list.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ListEntry {
+    /// Text shown on the page, usually a mention
+    pub line: String,
+    pub name: String,
+    /// Heading the entry is listed under, if the list is grouped
+    pub group: Option<String>,
+    pub count: usize,
+    /// Unix timestamp of the last activity
+    pub active: Option<i64>,
+}
+
+/// Keep entries whose name contains the search, ignoring case.
+pub fn filter_entries(entries: Vec<ListEntry>, search: Option<&str>) -> Vec<ListEntry> {
+    let search = match search.map(|it| it.trim().to_lowercase()) {
+        Some(search) if !search.is_empty() => search,
+        _ => return entries,
+    };
+    entries
+        .into_iter()
+        .filter(|it| it.name.to_lowercase().contains(&search))
+        .collect()
+}
+
+/// Sort entries, keeping groups together and the uncategorized group last.
+pub fn sort_entries(entries: &mut [ListEntry], order: SortOrder) {
+    entries.sort_by_key(|it| it.name.to_lowercase());
+    match order {
+        SortOrder::Name => {}
+        SortOrder::Subscribers => entries.sort_by(|a, b| b.count.cmp(&a.count)),
+        SortOrder::Activity => entries.sort_by(|a, b| b.active.cmp(&a.active)),
+    }
+    entries.sort_by_key(|it| {
+        it.group
+            .as_ref()
+            .map(|group| (group == UNCATEGORIZED, group.to_lowercase()))
+    });
+}
+
+/// Split entries into page descriptions, repeating the group heading on every page.
+pub fn paginate(entries: &[ListEntry], per_page: usize, limit: usize) -> Vec<String> {
+    let heading = |group: &Option<String>| {
+        group
+            .as_ref()
+            .map(|it| format!("**{}**\n", it))
+            .unwrap_or_default()
+    };
+
+    let mut pages = Vec::new();
+    let mut page = String::new();
+    let mut count = 0;
+    let mut group = None;
+    for entry in entries {
+        let mut prefix = if count == 0 || group != entry.group.as_ref() {
+            heading(&entry.group)
+        } else {
+            String::new()
+        };
+        if count > 0
+            && (count == per_page || page.len() + prefix.len() + entry.line.len() + 1 > limit)
+        {
+            pages.push(std::mem::take(&mut page));
+            count = 0;
+            prefix = heading(&entry.group);
+        }
+        page += &prefix;
+        page += &entry.line;
+        page.push('\n');
+        count += 1;
+        group = entry.group.as_ref();
+    }
+    if count > 0 {
+        pages.push(page);
+    }
+    pages
+}
+
+fn page_embed<'a>(
+    title: &'a str,
+    pages: &'a [String],
+    page: usize,
+) -> impl FnOnce(&mut CreateEmbed) -> &mut CreateEmbed + 'a {
+    move |f| {
+        f.title(title)
+            .color(Color::DARK_GREEN)
+            .description(&pages[page])
+            .footer(|f| f.text(format!("Page {}/{}", page + 1, pages.len())))
+    }
+}
+
+fn page_buttons(
+    page: usize,
+    pages: usize,
+) -> impl FnOnce(&mut CreateComponents) -> &mut CreateComponents {
+    move |f| {
+        if pages > 1 {
+            f.create_action_row(|f| {
+                f.create_button(|f| {
+                    f.custom_id(ComponentAction::PreviousPage.encode())
+                        .style(ButtonStyle::Secondary)
+                        .label("Previous")
+                        .disabled(page == 0)
+                })
+                .create_button(|f| {
+                    f.custom_id(ComponentAction::NextPage.encode())
+                        .style(ButtonStyle::Secondary)
+                        .label("Next")
+                        .disabled(page + 1 == pages)
+                })
+            });
+        }
+        f
+    }
+}
+
+/// Send pages as an embed that its author can page through with buttons.
+pub async fn send_pages(ctx: Context<'_>, title: String, pages: Vec<String>) -> Result<(), Error> {
+    let pages = if pages.is_empty() {
+        vec!["*Nothing found!*".to_string()]
+    } else {
+        pages
+    };
+
+    let mut page = 0;
+    let handle = ctx
+        .send(|f| {
+            f.embed(page_embed(&title, &pages, page))
+                .components(page_buttons(page, pages.len()))
+        })
+        .await?;
+    let handle = match handle {
+        Some(handle) if pages.len() > 1 => handle,
+        _ => return Ok(()),
+    };
+    let m = handle.message().await?;
+
+    while let Some(interaction) = m
+        .await_component_interaction(ctx.discord())
+        .timeout(Duration::from_secs(PAGE_TIMEOUT))
+        .await
+    {
+        if interaction.user.id != ctx.author().id {
+            interaction
+                .create_interaction_response(ctx.discord(), |f| {
+                    f.kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|f| {
+                            f.content("❌ Only the author of the command can change the page!")
+                                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                        })
+                })
+                .await?;
+            continue;
+        }
+        match ComponentAction::decode(&interaction.data.custom_id) {
+            Some(ComponentAction::PreviousPage) => page = page.saturating_sub(1),
+            Some(ComponentAction::NextPage) => page = (page + 1).min(pages.len() - 1),
+            _ => continue,
+        }
+        interaction
+            .create_interaction_response(ctx.discord(), |f| {
+                f.kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|f| {
+                        f.embed(page_embed(&title, &pages, page))
+                            .components(page_buttons(page, pages.len()))
+                    })
+            })
+            .await?;
+    }
+
+    // the buttons stop working once nobody listens to them anymore
+    handle.edit(ctx, |f| f.components(|f| f)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pages::*;
+
+    fn entry(name: &str, group: Option<&str>, count: usize, active: Option<i64>) -> ListEntry {
+        ListEntry {
+            line: name.to_string(),
+            name: name.to_string(),
+            group: group.map(|it| it.to_string()),
+            count,
+            active,
+        }
+    }
+
+    #[test]
+    pub fn test_sort_entries() {
+        let mut entries = vec![
+            entry("b", None, 1, Some(3)),
+            entry("A", None, 2, None),
+            entry("c", None, 2, Some(5)),
+        ];
+        sort_entries(&mut entries, SortOrder::Name);
+        assert_eq!(entries[0].name, "A");
+        sort_entries(&mut entries, SortOrder::Subscribers);
+        assert_eq!(entries[0].name, "A");
+        assert_eq!(entries[2].name, "b");
+        sort_entries(&mut entries, SortOrder::Activity);
+        assert_eq!(entries[0].name, "c");
+        assert_eq!(entries[2].name, "A");
+
+        let mut grouped = vec![
+            entry("a", Some(UNCATEGORIZED), 0, None),
+            entry("c", Some("fps"), 0, None),
+            entry("b", Some("FPS"), 0, None),
+        ];
+        sort_entries(&mut grouped, SortOrder::Name);
+        assert_eq!(grouped[0].name, "b");
+        assert_eq!(grouped[2].name, "a");
+    }
+
+    #[test]
+    pub fn test_filter_entries() {
+        let entries = vec![
+            entry("Apex Legends", None, 0, None),
+            entry("CS", None, 0, None),
+        ];
+        assert_eq!(filter_entries(entries.clone(), Some("apex")).len(), 1);
+        assert_eq!(filter_entries(entries.clone(), Some(" ")).len(), 2);
+        assert_eq!(filter_entries(entries, None).len(), 2);
+    }
+
+    #[test]
+    pub fn test_paginate() {
+        let entries: Vec<_> = (0..5)
+            .map(|i| entry(&i.to_string(), Some(if i < 3 { "A" } else { "B" }), 0, None))
+            .collect();
+        assert_eq!(
+            paginate(&entries, 2, DESCRIPTION_LIMIT),
+            vec!["**A**\n0\n1\n", "**A**\n2\n**B**\n3\n", "**B**\n4\n"]
+        );
+        assert_eq!(
+            paginate(&entries, 10, 12),
+            vec!["**A**\n0\n1\n2\n", "**B**\n3\n4\n"]
+        );
+        assert!(paginate(&[], 2, DESCRIPTION_LIMIT).is_empty());
+    }
+}
@@ -0,0 +1,420 @@
+use log::{error, info};
+use poise::serenity_prelude::{
+    ChannelId, Color, Context as SerenityContext, CreateComponents, CreateEmbed, GuildId,
+    Interaction, InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+    MessageBuilder, MessageId, RoleId,
+};
+use serde_derive::{Deserialize, Serialize};
+use tqdb::{remove, search};
+
+use crate::api::{self, Snowflake, Table};
+use crate::categories::group_by_category;
+use crate::components::ComponentAction;
+use crate::managed::check_game_role_id;
+use crate::stats::{record, RoleEventKind};
+use crate::util::*;
+use crate::{Context, Data, Error};
+
+/// Discord allows this many options in a select menu.
+const MENU_OPTIONS: usize = 25;
+/// Discord allows this many action rows in a message.
+const MENUS: usize = 5;
+
+/// A message that members use to pick their roles themselves.
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+pub struct Panel {
+    pub guild_id: api::GuildId,
+    pub channel_id: Snowflake,
+    pub message_id: Snowflake,
+    /// Only roles of this category are listed, or every role when empty
+    #[serde(default)]
+    pub category: Option<String>,
+}
+
+fn same_category(a: &Option<String>, b: &Option<String>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.eq_ignore_ascii_case(b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+impl Table<Panel> {
+    /// Replace the panel of a guild for the same category, returning the previous one.
+    pub fn set_panel(&mut self, panel: Panel) -> Option<Panel> {
+        let guild_id = panel.guild_id;
+        let category = panel.category.clone();
+        let old = remove!(&mut self.0 => move |it: &Panel| it.guild_id == guild_id && same_category(&it.category, &category))
+            .next();
+        let _ = self.0.insert_unique(panel);
+        old
+    }
+
+    pub fn remove_panel<M: Into<Snowflake>>(&mut self, message_id: M) -> Option<Panel> {
+        let message_id = message_id.into();
+        remove!(&mut self.0 => move |it: &Panel| it.message_id == message_id).next()
+    }
+
+    pub fn show_panels_of_guild<G: Into<api::GuildId>>(&self, guild_id: G) -> Vec<Panel> {
+        let guild_id = guild_id.into();
+        search!(&self.0 => move |it: &Panel| it.guild_id == guild_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Keep panels of a renamed category listing it.
+    pub fn rename_category<G: Into<api::GuildId>>(&mut self, guild_id: G, from: &str, to: &str) {
+        let guild_id = guild_id.into();
+        let from = Some(from.trim().to_string());
+        let renamed: Vec<Panel> = remove!(&mut self.0 => move |it: &Panel| it.guild_id == guild_id && same_category(&it.category, &from))
+            .collect();
+        for mut panel in renamed {
+            panel.category = Some(to.trim().to_string());
+            let _ = self.0.insert_unique(panel);
+        }
+    }
+}
+
+/// Split groups of roles into select menus, returning them with how many roles didn't fit.
+pub fn panel_menus(
+    groups: Vec<(String, Vec<(api::RoleId, String)>)>,
+) -> (Vec<(String, Vec<(api::RoleId, String)>)>, usize) {
+    let mut menus: Vec<(String, Vec<(api::RoleId, String)>)> = groups
+        .into_iter()
+        .flat_map(|(name, roles)| {
+            roles
+                .chunks(MENU_OPTIONS)
+                .map(|chunk| (name.clone(), chunk.to_vec()))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let left_out = menus.iter().skip(MENUS).map(|(_, roles)| roles.len()).sum();
+    menus.truncate(MENUS);
+    (menus, left_out)
+}
+
+/// Tracked game roles of a guild that still exist, grouped by category.
+fn roles_of_panel(
+    ctx: &SerenityContext,
+    user_data: &Data,
+    guild_id: GuildId,
+    category: &Option<String>,
+) -> Vec<(String, Vec<(api::RoleId, String)>)> {
+    let tracked: Vec<api::RoleId> = user_data
+        .roles
+        .lock()
+        .unwrap()
+        .show_roles_of_guild(guild_id.0)
+        .into_iter()
+        .copied()
+        .collect();
+    let roles = tracked
+        .into_iter()
+        .filter(|id| check_game_role_id(ctx, user_data, *id).is_ok())
+        .filter_map(|id| {
+            RoleId::from(id)
+                .to_role_cached(ctx)
+                .map(|role| (id, role.name))
+        })
+        .collect();
+    let categories = user_data
+        .categories
+        .lock()
+        .unwrap()
+        .show_categories_of_guild(guild_id.0);
+
+    group_by_category(roles, &categories)
+        .into_iter()
+        .filter(|(name, _)| {
+            category
+                .as_ref()
+                .map_or(true, |category| category.eq_ignore_ascii_case(name))
+        })
+        .collect()
+}
+
+fn panel_embed(
+    menus: &[(String, Vec<(api::RoleId, String)>)],
+    category: &Option<String>,
+) -> impl FnOnce(&mut CreateEmbed) -> &mut CreateEmbed {
+    let mut description = MessageBuilder::new();
+    description.push_line("Pick roles below to join them, or pick them again to leave.");
+    if menus.is_empty() {
+        description.push_italic("No roles yet! Create one with `/game create`.");
+    }
+    let description = description.build();
+    let title = match category {
+        Some(category) => format!("🎮 {} roles", category),
+        None => "🎮 Game roles".into(),
+    };
+
+    move |f| {
+        f.title(title)
+            .color(Color::DARK_GREEN)
+            .description(description)
+    }
+}
+
+fn panel_components(
+    menus: Vec<(String, Vec<(api::RoleId, String)>)>,
+) -> impl FnOnce(&mut CreateComponents) -> &mut CreateComponents {
+    move |f| {
+        for (i, (category, menu)) in menus.into_iter().enumerate() {
+            f.create_action_row(|f| {
+                f.create_select_menu(|f| {
+                    f.custom_id(ComponentAction::Panel(i).encode())
+                        .placeholder(format!("{}: pick roles to join or leave", category))
+                        .min_values(1)
+                        .max_values(menu.len() as u64)
+                        .options(|f| {
+                            for (id, name) in menu {
+                                f.create_option(|f| f.label(name).value(id));
+                            }
+                            f
+                        })
+                })
+            });
+        }
+        f
+    }
+}
+
+/// Post a panel for members to pick their roles, replacing the previous one
+#[poise::command(
+    slash_command,
+    category = "game",
+    required_permissions = "MANAGE_ROLES",
+    ephemeral = true
+)]
+pub async fn panel(
+    ctx: Context<'_>,
+    #[description = "Only list the roles of this category"] category: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let category = category.map(|it| it.trim().to_string());
+    let (menus, left_out) = panel_menus(roles_of_panel(
+        ctx.discord(),
+        ctx.data(),
+        guild_id,
+        &category,
+    ));
+    let m = ctx
+        .channel_id()
+        .send_message(ctx.discord(), |f| {
+            f.embed(panel_embed(&menus, &category))
+                .components(panel_components(menus))
+        })
+        .await?;
+
+    let old = ctx.data().panels.lock().unwrap().set_panel(Panel {
+        guild_id: guild_id.0,
+        channel_id: m.channel_id.0,
+        message_id: m.id.0,
+        category,
+    });
+    if let Some(old) = old {
+        // the old panel may already be gone, which is fine
+        let _ = ChannelId::from(old.channel_id)
+            .delete_message(ctx.discord(), MessageId::from(old.message_id))
+            .await;
+    }
+    save_table("panels", &ctx.data().panels.lock().unwrap());
+
+    info!(
+        "({}) {} posted a role panel in {}!",
+        guild_id,
+        ctx.author().id,
+        m.channel_id
+    );
+    let message = if left_out == 0 {
+        "Posted the panel!".to_string()
+    } else {
+        format!(
+            "Posted the panel, but {} roles didn't fit! *Post a panel per category with `/game panel category:` instead.*",
+            left_out
+        )
+    };
+    ctx.send(|f| f.embed(successful_interaction(|f| f.description(message))))
+        .await?;
+
+    Ok(())
+}
+
+/// Discord's error codes for a channel or a message that doesn't exist.
+const UNKNOWN_CHANNEL: isize = 10003;
+const UNKNOWN_MESSAGE: isize = 10008;
+
+/// Whether Discord answered that a message or its channel was deleted.
+pub fn is_deleted(status: u16, code: isize) -> bool {
+    status == 404 && (code == UNKNOWN_CHANNEL || code == UNKNOWN_MESSAGE)
+}
+
+fn is_deleted_error(error: &poise::serenity_prelude::SerenityError) -> bool {
+    match error {
+        poise::serenity_prelude::SerenityError::Http(e) => match &**e {
+            poise::serenity_prelude::HttpError::UnsuccessfulRequest(response) => {
+                is_deleted(response.status_code.as_u16(), response.error.code)
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Update the panels of a guild after its roles changed.
+///
+/// Panels are forgotten only when their message or channel was deleted.
+pub async fn refresh_panels(ctx: &SerenityContext, user_data: &Data, guild_id: GuildId) {
+    let panels = user_data
+        .panels
+        .lock()
+        .unwrap()
+        .show_panels_of_guild(guild_id.0);
+
+    for panel in panels {
+        let (menus, left_out) =
+            panel_menus(roles_of_panel(ctx, user_data, guild_id, &panel.category));
+        if left_out > 0 {
+            info!(
+                "({}) {} roles didn't fit on panel {}!",
+                guild_id, left_out, panel.message_id
+            );
+        }
+        let edit = ChannelId::from(panel.channel_id)
+            .edit_message(ctx, MessageId::from(panel.message_id), |f| {
+                f.embed(panel_embed(&menus, &panel.category))
+                    .components(panel_components(menus))
+            })
+            .await;
+
+        let e = match edit {
+            Ok(_) => continue,
+            Err(e) => e,
+        };
+        error!("Failed to update a panel of {}! {}", guild_id, e);
+        // the panel was removed, it can be posted again with `/game panel`
+        if is_deleted_error(&e) {
+            user_data
+                .panels
+                .lock()
+                .unwrap()
+                .remove_panel(panel.message_id);
+            save_table("panels", &user_data.panels.lock().unwrap());
+        }
+    }
+}
+
+pub async fn interaction_panel(
+    ctx: &SerenityContext,
+    user_data: &Data,
+    interaction: &Interaction,
+) -> Result<(), poise::serenity_prelude::SerenityError> {
+    let m = match interaction.clone().message_component() {
+        Some(m) => m,
+        None => return Ok(()),
+    };
+    let guild_id = match m.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let mut response = MessageBuilder::new();
+    for role_id in m.data.values.iter().filter_map(|v| v.parse::<u64>().ok()) {
+        // leaving is always allowed, even roles that stopped being game roles
+        let refusal = check_game_role_id(ctx, user_data, role_id).err();
+        let mut roles = user_data.roles.lock().unwrap();
+        let left = roles
+            .remove_user_from_role(guild_id.0, role_id, m.user.id.0)
+            .is_ok();
+        if !left {
+            if let Some(refusal) = refusal {
+                drop(roles);
+                response
+                    .push("❌ Can't join ")
+                    .role(RoleId::from(role_id))
+                    .push_line(format!("! {}", refusal));
+                continue;
+            }
+            if roles
+                .add_user_to_role(guild_id.0, role_id, m.user.id.0)
+                .is_err()
+            {
+                continue;
+            }
+        }
+        drop(roles);
+
+        let kind = if left {
+            RoleEventKind::Leave
+        } else {
+            RoleEventKind::Join
+        };
+        record(user_data, guild_id, role_id, m.user.id, kind);
+        info!(
+            "({}) {} {} {} from the panel!",
+            guild_id,
+            m.user.id,
+            if left { "left" } else { "joined" },
+            role_id
+        );
+        response
+            .push(if left { "➖ Left " } else { "➕ Joined " })
+            .role(RoleId::from(role_id))
+            .push_line("");
+    }
+    save_roles(user_data);
+
+    let response = response.build();
+    m.create_interaction_response(ctx, |f| {
+        f.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|f| {
+                f.content(if response.is_empty() {
+                    "Nothing changed!".into()
+                } else {
+                    response
+                })
+                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+            })
+    })
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::panels::*;
+
+    #[test]
+    pub fn test_is_deleted() {
+        assert!(is_deleted(404, 10008));
+        assert!(is_deleted(404, 10003));
+        assert!(!is_deleted(403, 50001));
+        assert!(!is_deleted(500, 0));
+    }
+
+    #[test]
+    pub fn test_panel_menus() {
+        let groups = vec![
+            ("FPS".to_string(), vec![(1, "CS".to_string())]),
+            ("Other".to_string(), vec![(2, "chess".to_string())]),
+        ];
+        assert_eq!(panel_menus(groups.clone()), (groups, 0));
+
+        let many = (0..200).map(|i| (i, format!("{:03}", i))).collect();
+        let (menus, left_out) = panel_menus(vec![
+            ("FPS".to_string(), vec![(1, "CS".to_string())]),
+            ("Other".to_string(), many),
+        ]);
+        assert_eq!(menus.len(), MENUS);
+        assert_eq!(left_out, 200 - 4 * MENU_OPTIONS);
+        assert_eq!(menus[0].1.len(), 1);
+        assert!(menus[1..]
+            .iter()
+            .all(|(_, menu)| menu.len() == MENU_OPTIONS));
+        assert_eq!(menus[2], ("Other".to_string(), menus[2].1.clone()));
+        assert_eq!(menus[2].1[0], (25, "025".to_string()));
+    }
+}
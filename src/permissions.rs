@@ -0,0 +1,279 @@
+use log::info;
+use poise::serenity_prelude::{MessageBuilder, Role, RoleId, User, UserId};
+use serde_derive::{Deserialize, Serialize};
+use tqdb::{remove, search};
+
+use crate::api::{self, ApiError, GuildId, Table};
+use crate::util::*;
+use crate::{Context, Error};
+
+/// A single rule allowing someone to ping a hidden role.
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+pub enum PingRule {
+    SubscribersOnly,
+    AllowRole(api::RoleId),
+    AllowUser(api::UserId),
+}
+
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+pub struct PingPermission {
+    guild_id: GuildId,
+    role_id: api::RoleId,
+    rule: PingRule,
+}
+
+impl Table<PingPermission> {
+    pub fn add_rule<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &mut self,
+        guild_id: G,
+        role_id: R,
+        rule: PingRule,
+    ) -> Result<(), ApiError> {
+        self.0
+            .insert_unique(PingPermission {
+                guild_id: guild_id.into(),
+                role_id: role_id.into(),
+                rule,
+            })
+            .map_err(|_| ApiError::Insertion)
+    }
+
+    pub fn remove_rule<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &mut self,
+        guild_id: G,
+        role_id: R,
+        rule: PingRule,
+    ) -> Result<PingRule, ApiError> {
+        let guild_id = guild_id.into();
+        let role_id = role_id.into();
+        remove!(&mut self.0 => move |it: &PingPermission| it.guild_id == guild_id && it.role_id == role_id && it.rule == rule)
+            .next()
+            .map(|it| it.rule)
+            .ok_or(ApiError::Removal)
+    }
+
+    pub fn clear_rules<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &mut self,
+        guild_id: G,
+        role_id: R,
+    ) -> Vec<PingRule> {
+        let guild_id = guild_id.into();
+        let role_id = role_id.into();
+        remove!(&mut self.0 => move |it: &PingPermission| it.guild_id == guild_id && it.role_id == role_id)
+            .map(|it| it.rule)
+            .collect()
+    }
+
+    /// Move the rules of a role, and rules allowing it, to another role after a merge.
+    pub fn move_role<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &mut self,
+        guild_id: G,
+        from: R,
+        into: R,
+    ) -> usize {
+        let guild_id = guild_id.into();
+        let from = from.into();
+        let into = into.into();
+        let moved: Vec<PingPermission> = remove!(&mut self.0 => move |it: &PingPermission| it.guild_id == guild_id && (it.role_id == from || it.rule == PingRule::AllowRole(from)))
+            .collect();
+        for it in moved.iter() {
+            let role_id = if it.role_id == from { into } else { it.role_id };
+            let rule = match &it.rule {
+                PingRule::AllowRole(id) if *id == from => PingRule::AllowRole(into),
+                rule => rule.clone(),
+            };
+            // the role may already have the same rule
+            let _ = self.add_rule(guild_id, role_id, rule);
+        }
+        moved.len()
+    }
+
+    pub fn show_rules_of_role<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &self,
+        guild_id: G,
+        role_id: R,
+    ) -> Vec<&PingRule> {
+        let guild_id = guild_id.into();
+        let role_id = role_id.into();
+        search!(&self.0 => move |it: &PingPermission| it.guild_id == guild_id && it.role_id == role_id)
+            .map(|it| &it.rule)
+            .collect()
+    }
+}
+
+/// Whether the author of a message may ping a role with the given rules.
+///
+/// A role without any rules may be pinged by anyone.
+pub fn may_ping(
+    rules: &[&PingRule],
+    author: api::UserId,
+    author_roles: &[api::RoleId],
+    subscribed: bool,
+) -> bool {
+    rules.is_empty()
+        || rules.iter().any(|rule| match rule {
+            PingRule::SubscribersOnly => subscribed,
+            PingRule::AllowRole(id) => author_roles.contains(id),
+            PingRule::AllowUser(id) => *id == author,
+        })
+}
+
+#[derive(poise::SlashChoiceParameter)]
+pub enum PermissionAction {
+    #[name = "Anyone may ping"]
+    Anyone,
+    #[name = "Subscribers may ping"]
+    Subscribers,
+    #[name = "Allow the selected user or role"]
+    Allow,
+    #[name = "Revoke the selected user or role"]
+    Revoke,
+}
+
+fn describe_rules(role: &Role, rules: &[PingRule]) -> String {
+    let mut mb = MessageBuilder::new();
+    if rules.is_empty() {
+        mb.push("Anyone may ping ").role(role).push("!");
+        return mb.build();
+    }
+    mb.push("Only the following may ping ")
+        .role(role)
+        .push_line(":");
+    for rule in rules {
+        match rule {
+            PingRule::SubscribersOnly => mb.push_line("• Subscribers"),
+            PingRule::AllowRole(id) => mb.push("• ").role(RoleId::from(*id)).push_line(""),
+            PingRule::AllowUser(id) => mb.push("• ").user(UserId::from(*id)).push_line(""),
+        };
+    }
+    mb.build()
+}
+
+/// Show or change who may ping a role
+#[poise::command(
+    slash_command,
+    category = "game",
+    required_permissions = "MANAGE_ROLES"
+)]
+pub async fn permissions(
+    ctx: Context<'_>,
+    #[description = "Selected role"] role: Role,
+    #[description = "Change to make"] action: Option<PermissionAction>,
+    #[description = "User to allow or revoke"] user: Option<User>,
+    #[description = "Role to allow or revoke"] allowed_role: Option<Role>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let mut targets = Vec::new();
+    if let Some(u) = &user {
+        targets.push(PingRule::AllowUser(u.id.0));
+    }
+    if let Some(r) = &allowed_role {
+        targets.push(PingRule::AllowRole(r.id.0));
+    }
+
+    if matches!(action, Some(PermissionAction::Allow)) && targets.is_empty() {
+        ctx.send(|f| {
+            f.embed(unsuccessful_interaction(|f| {
+                f.description("Failed to allow anyone! *Select a user or a role to allow.*")
+            }))
+        })
+        .await?;
+        return Ok(());
+    }
+
+    if let Some(action) = action {
+        let mut db = ctx.data().permissions.lock().unwrap();
+        match action {
+            PermissionAction::Anyone => {
+                db.clear_rules(guild_id, role.id);
+            }
+            PermissionAction::Subscribers => {
+                let _ = db.add_rule(guild_id, role.id, PingRule::SubscribersOnly);
+            }
+            PermissionAction::Allow => {
+                for rule in targets.iter().cloned() {
+                    let _ = db.add_rule(guild_id, role.id, rule);
+                }
+            }
+            PermissionAction::Revoke => {
+                if targets.is_empty() {
+                    targets.push(PingRule::SubscribersOnly);
+                }
+                for rule in targets.iter().cloned() {
+                    let _ = db.remove_rule(guild_id, role.id, rule);
+                }
+            }
+        }
+        drop(db);
+
+        info!(
+            "({}) {} changed ping permissions of {}!",
+            guild_id,
+            ctx.author().id,
+            role.id
+        );
+        save_table("permissions", &ctx.data().permissions.lock().unwrap());
+    }
+
+    let rules: Vec<PingRule> = ctx
+        .data()
+        .permissions
+        .lock()
+        .unwrap()
+        .show_rules_of_role(guild_id, role.id)
+        .into_iter()
+        .cloned()
+        .collect();
+
+    let message = describe_rules(&role, &rules);
+    ctx.send(|f| f.embed(successful_interaction(|f| f.description(message))))
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::permissions::*;
+    use tqdb::Database;
+
+    #[test]
+    pub fn test_may_ping_without_rules() {
+        assert!(may_ping(&[], 1, &[], false));
+    }
+
+    #[test]
+    pub fn test_may_ping_with_rules() {
+        let subscribers = PingRule::SubscribersOnly;
+        let moderators = PingRule::AllowRole(10);
+        let user = PingRule::AllowUser(3);
+        let rules = vec![&subscribers, &moderators, &user];
+
+        assert!(may_ping(&rules, 1, &[], true));
+        assert!(may_ping(&rules, 2, &[10], false));
+        assert!(may_ping(&rules, 3, &[], false));
+        assert!(!may_ping(&rules, 4, &[11], false));
+    }
+
+    #[test]
+    pub fn test_move_role() {
+        let mut table = Table(Database::from(Vec::new()));
+        let _ = table.add_rule(1u64, 1u64, PingRule::SubscribersOnly);
+        let _ = table.add_rule(1u64, 1u64, PingRule::AllowUser(5));
+        let _ = table.add_rule(1u64, 2u64, PingRule::SubscribersOnly);
+        let _ = table.add_rule(1u64, 3u64, PingRule::AllowRole(1));
+
+        assert_eq!(table.move_role(1u64, 1u64, 2u64), 3);
+        assert!(table.show_rules_of_role(1u64, 1u64).is_empty());
+        // the duplicate subscribers rule is dropped
+        assert_eq!(table.show_rules_of_role(1u64, 2u64).len(), 2);
+        assert_eq!(
+            table.show_rules_of_role(1u64, 3u64),
+            vec![&PingRule::AllowRole(2)]
+        );
+    }
+}
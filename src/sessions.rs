@@ -0,0 +1,624 @@
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use log::{error, info};
+use poise::serenity_prelude::{
+    ButtonStyle, ChannelId, Color, Context as SerenityContext, CreateComponents, CreateEmbed,
+    Interaction, InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+    Mentionable, MessageBuilder, MessageId, ReactionType, Role, RoleId, UserId,
+};
+use serde_derive::{Deserialize, Serialize};
+use tqdb::{remove, search};
+
+use crate::api::{self, ApiError, GuildId, Snowflake, Table};
+use crate::components::ComponentAction;
+use crate::events::populate_thread;
+use crate::game::ensure_game_role;
+use crate::metrics::THREADS_CREATED;
+use crate::util::*;
+use crate::{Context, Data, Error};
+
+pub type SessionId = u64;
+
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone, Copy)]
+pub enum RsvpChoice {
+    Join,
+    Maybe,
+    Decline,
+}
+
+impl RsvpChoice {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RsvpChoice::Join => "join",
+            RsvpChoice::Maybe => "maybe",
+            RsvpChoice::Decline => "decline",
+        }
+    }
+
+    pub fn from_str(text: &str) -> Option<Self> {
+        match text {
+            "join" => Some(RsvpChoice::Join),
+            "maybe" => Some(RsvpChoice::Maybe),
+            "decline" => Some(RsvpChoice::Decline),
+            _ => None,
+        }
+    }
+}
+
+/// A game session that subscribers can RSVP to.
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+pub struct Session {
+    pub id: SessionId,
+    pub guild_id: GuildId,
+    pub channel_id: Snowflake,
+    pub message_id: Snowflake,
+    pub role_id: api::RoleId,
+    pub host_id: api::UserId,
+    /// Unix timestamp of the start of the session
+    pub time: i64,
+    pub note: Option<String>,
+    /// Minutes before the session to remind everyone
+    pub remind_before: i64,
+    pub reminded: bool,
+}
+
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+pub struct Rsvp {
+    session_id: SessionId,
+    user_id: api::UserId,
+    choice: RsvpChoice,
+}
+
+impl Table<Session> {
+    pub fn add_session(&mut self, session: Session) -> Result<(), ApiError> {
+        self.0
+            .insert_unique(session)
+            .map_err(|_| ApiError::Insertion)
+    }
+
+    pub fn session(&self, id: SessionId) -> Option<&Session> {
+        search!(&self.0 => move |it: &Session| it.id == id).next()
+    }
+
+    pub fn remove_session(&mut self, id: SessionId) -> Result<Session, ApiError> {
+        remove!(&mut self.0 => move |it: &Session| it.id == id)
+            .next()
+            .ok_or(ApiError::Removal)
+    }
+
+    /// Sessions that should be reminded about, but haven't been yet.
+    pub fn due_sessions(&self, now: i64) -> Vec<Session> {
+        search!(&self.0 => move |it: &Session| !it.reminded && now >= it.time.saturating_sub(it.remind_before.saturating_mul(60)))
+            .cloned()
+            .collect()
+    }
+
+    /// Sessions that started more than a day ago.
+    pub fn expired_sessions(&self, now: i64) -> Vec<SessionId> {
+        search!(&self.0 => move |it: &Session| it.time.saturating_add(24 * 60 * 60) < now)
+            .map(|it| it.id)
+            .collect()
+    }
+
+    /// Point the sessions of a role at another role after a merge.
+    pub fn move_role<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &mut self,
+        guild_id: G,
+        from: R,
+        into: R,
+    ) -> usize {
+        let guild_id = guild_id.into();
+        let from = from.into();
+        let into = into.into();
+        let moved: Vec<Session> = remove!(&mut self.0 => move |it: &Session| it.guild_id == guild_id && it.role_id == from)
+            .collect();
+        for session in moved.iter() {
+            let _ = self.add_session(Session {
+                role_id: into,
+                ..session.clone()
+            });
+        }
+        moved.len()
+    }
+
+    pub fn mark_reminded(&mut self, id: SessionId) {
+        if let Ok(mut session) = self.remove_session(id) {
+            session.reminded = true;
+            let _ = self.add_session(session);
+        }
+    }
+}
+
+impl Table<Rsvp> {
+    pub fn set_rsvp<U: Into<api::UserId>>(
+        &mut self,
+        session_id: SessionId,
+        user_id: U,
+        choice: RsvpChoice,
+    ) -> Result<(), ApiError> {
+        let user_id = user_id.into();
+        remove!(&mut self.0 => move |it: &Rsvp| it.session_id == session_id && it.user_id == user_id)
+            .for_each(drop);
+        self.0
+            .insert_unique(Rsvp {
+                session_id,
+                user_id,
+                choice,
+            })
+            .map_err(|_| ApiError::Insertion)
+    }
+
+    pub fn show_rsvps_of_session(&self, session_id: SessionId) -> Vec<(api::UserId, RsvpChoice)> {
+        search!(&self.0 => move |it: &Rsvp| it.session_id == session_id)
+            .map(|it| (it.user_id, it.choice))
+            .collect()
+    }
+
+    pub fn remove_rsvps_of_session(&mut self, session_id: SessionId) -> Vec<api::UserId> {
+        remove!(&mut self.0 => move |it: &Rsvp| it.session_id == session_id)
+            .map(|it| it.user_id)
+            .collect()
+    }
+}
+
+/// Parse when a session starts, like `in 2h`, `1h30m`, `20:30` or `2022-02-03 20:30`.
+///
+/// Times without a date are the next time the clock reads that in the guild's timezone.
+/// Times more than a year away are refused.
+pub fn parse_session_time(text: &str, now: DateTime<FixedOffset>) -> Option<DateTime<Utc>> {
+    parse_time(text, now).filter(|time| *time - now.with_timezone(&Utc) <= max_schedule_ahead())
+}
+
+/// How far ahead sessions can be scheduled.
+fn max_schedule_ahead() -> chrono::Duration {
+    chrono::Duration::days(365)
+}
+
+fn parse_time(text: &str, now: DateTime<FixedOffset>) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    let relative = text.strip_prefix("in ").unwrap_or(text).replace(' ', "");
+
+    if let Some(minutes) = parse_duration_minutes(&relative) {
+        if minutes > max_schedule_ahead().num_minutes() {
+            return None;
+        }
+        return now
+            .checked_add_signed(chrono::Duration::minutes(minutes))
+            .map(|it| it.with_timezone(&Utc));
+    }
+
+    if let Ok(time) = NaiveTime::parse_from_str(text, "%H:%M") {
+        let today = now.date().and_time(time)?;
+        let time = if today <= now {
+            today + chrono::Duration::days(1)
+        } else {
+            today
+        };
+        return Some(time.with_timezone(&Utc));
+    }
+
+    let time = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").ok()?;
+    now.offset()
+        .from_local_datetime(&time)
+        .single()
+        .map(|it| it.with_timezone(&Utc))
+}
+
+/// Parse durations like `2h`, `45m` or `1h30m` into minutes.
+fn parse_duration_minutes(text: &str) -> Option<i64> {
+    if text.is_empty() {
+        return None;
+    }
+    let mut minutes = 0;
+    let mut number = String::new();
+    for c in text.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'h' | 'm' if !number.is_empty() => {
+                let n: i64 = number.parse().ok()?;
+                let n = if c == 'h' { n.checked_mul(60)? } else { n };
+                minutes = n.checked_add(minutes)?;
+                number.clear();
+            }
+            _ => return None,
+        }
+    }
+    if number.is_empty() {
+        Some(minutes)
+    } else {
+        None
+    }
+}
+
+fn session_embed(
+    session: &Session,
+    rsvps: &[(api::UserId, RsvpChoice)],
+) -> impl FnOnce(&mut CreateEmbed) -> &mut CreateEmbed {
+    let description = MessageBuilder::new()
+        .role(RoleId::from(session.role_id))
+        .push(" session hosted by ")
+        .user(UserId::from(session.host_id))
+        .push_line("!")
+        .push_line(session.note.clone().unwrap_or_default())
+        .build();
+
+    let list = |choice: RsvpChoice| {
+        let mentions: Vec<String> = rsvps
+            .iter()
+            .filter(|(_, c)| *c == choice)
+            .map(|(u, _)| UserId::from(*u).mention().to_string())
+            .collect();
+        (
+            mentions.len(),
+            if mentions.is_empty() {
+                "-".to_string()
+            } else {
+                join_truncated(&mentions, FIELD_LIMIT)
+            },
+        )
+    };
+    let going = list(RsvpChoice::Join);
+    let maybe = list(RsvpChoice::Maybe);
+    let declined = list(RsvpChoice::Decline);
+    let time = session.time;
+
+    move |f| {
+        f.title("📅 Game session")
+            .color(Color::DARK_GREEN)
+            .description(description)
+            .field("When", format!("<t:{0}:F> (<t:{0}:R>)", time), false)
+            .field(format!("Going ({})", going.0), going.1, true)
+            .field(format!("Maybe ({})", maybe.0), maybe.1, true)
+            .field(format!("Declined ({})", declined.0), declined.1, true)
+    }
+}
+
+fn session_buttons(
+    session_id: SessionId,
+    disabled: bool,
+) -> impl FnOnce(&mut CreateComponents) -> &mut CreateComponents {
+    move |f| {
+        f.create_action_row(|f| {
+            for (choice, emoji, label, style) in [
+                (RsvpChoice::Join, '✅', "Join", ButtonStyle::Success),
+                (RsvpChoice::Maybe, '❔', "Maybe", ButtonStyle::Secondary),
+                (RsvpChoice::Decline, '❌', "Decline", ButtonStyle::Danger),
+            ] {
+                f.create_button(|f| {
+                    f.custom_id(ComponentAction::Rsvp(session_id, choice).encode())
+                        .emoji(ReactionType::from(emoji))
+                        .style(style)
+                        .label(label)
+                        .disabled(disabled)
+                });
+            }
+            f
+        })
+    }
+}
+
+/// Schedule a session that subscribers can RSVP to
+#[poise::command(slash_command, category = "game")]
+pub async fn schedule(
+    ctx: Context<'_>,
+    #[description = "Selected role"] role: Role,
+    #[description = "When, like \"in 2h\", \"20:30\" or \"2022-02-03 20:30\""] time: String,
+    #[description = "Note for the session"] note: Option<String>,
+    #[description = "Minutes before the session to remind everyone (default 15)"] reminder: Option<
+        u32,
+    >,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    if !ensure_game_role(&ctx, &role).await? {
+        return Ok(());
+    }
+
+    let timezone = ctx
+        .data()
+        .settings
+        .lock()
+        .unwrap()
+        .settings_of_guild(guild_id)
+        .timezone();
+
+    let time = match parse_session_time(&time, Utc::now().with_timezone(&timezone)) {
+        Some(time) if time > Utc::now() => time,
+        _ => {
+            ctx.send(|f| {
+                f.embed(unsuccessful_interaction(|f| {
+                    f.description(format!("I don't understand when {} is!", time))
+                }))
+            })
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let mut session = Session {
+        id: 0,
+        guild_id: guild_id.0,
+        channel_id: ctx.channel_id().0,
+        message_id: 0,
+        role_id: role.id.0,
+        host_id: ctx.author().id.0,
+        time: time.timestamp(),
+        note,
+        remind_before: reminder.unwrap_or(15) as i64,
+        reminded: false,
+    };
+
+    let m = ctx.send(|f| f.embed(session_embed(&session, &[]))).await?;
+
+    let m = match m {
+        Some(m) => m.message().await?,
+        None => return Ok(()),
+    };
+
+    // message ids never repeat, so buttons of an old session can't reach a newer one
+    session.id = m.id.0;
+    session.message_id = m.id.0;
+    m.channel_id
+        .edit_message(ctx.discord(), m.id, |f| {
+            f.components(session_buttons(session.id, false))
+        })
+        .await?;
+    info!(
+        "({}) {} scheduled session {} for {}!",
+        guild_id,
+        ctx.author().id,
+        session.id,
+        role.id
+    );
+    ctx.data().sessions.lock().unwrap().add_session(session)?;
+    save_table("sessions", &ctx.data().sessions.lock().unwrap());
+
+    Ok(())
+}
+
+pub async fn interaction_rsvp(
+    ctx: &SerenityContext,
+    user_data: &Data,
+    interaction: &Interaction,
+    session_id: SessionId,
+    choice: RsvpChoice,
+) -> Result<(), poise::serenity_prelude::SerenityError> {
+    let m = match interaction.clone().message_component() {
+        Some(m) => m,
+        None => return Ok(()),
+    };
+
+    let session = user_data
+        .sessions
+        .lock()
+        .unwrap()
+        .session(session_id)
+        .cloned();
+    let session = match session {
+        Some(session) => session,
+        None => {
+            return m
+                .create_interaction_response(ctx, |f| {
+                    f.kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|f| {
+                            f.content("❌ This session is over!")
+                                .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                        })
+                })
+                .await
+        }
+    };
+
+    let rsvps = {
+        let mut rsvps = user_data.rsvps.lock().unwrap();
+        let _ = rsvps.set_rsvp(session_id, m.user.id.0, choice);
+        rsvps.show_rsvps_of_session(session_id)
+    };
+
+    info!(
+        "({}) {} answered {} to session {}!",
+        session.guild_id,
+        m.user.id,
+        choice.as_str(),
+        session_id
+    );
+    save_table("rsvps", &user_data.rsvps.lock().unwrap());
+
+    m.create_interaction_response(ctx, |f| {
+        f.kind(InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|f| {
+                f.embed(session_embed(&session, &rsvps))
+                    .components(session_buttons(session_id, session.reminded))
+            })
+    })
+    .await?;
+    Ok(())
+}
+
+/// Remind everyone about sessions that are about to start, forever.
+pub async fn run_session_reminders(ctx: SerenityContext, user_data: Data) {
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+    loop {
+        interval.tick().await;
+        let now = Utc::now().timestamp();
+
+        let due = user_data.sessions.lock().unwrap().due_sessions(now);
+        for session in due {
+            // don't ping about sessions that ended while we were offline
+            if session.time + 60 * 60 > now {
+                if let Err(e) = remind_session(&ctx, &user_data, &session).await {
+                    error!("Failed to remind about session {}! {}", session.id, e);
+                }
+            }
+            user_data.sessions.lock().unwrap().mark_reminded(session.id);
+            save_table("sessions", &user_data.sessions.lock().unwrap());
+
+            let edit = ChannelId::from(session.channel_id)
+                .edit_message(&ctx, MessageId::from(session.message_id), |f| {
+                    f.components(session_buttons(session.id, true))
+                })
+                .await;
+            if let Err(e) = edit {
+                error!("Failed to close the RSVPs of session {}! {}", session.id, e);
+            }
+        }
+
+        let expired = user_data.sessions.lock().unwrap().expired_sessions(now);
+        for id in expired.iter() {
+            let session = user_data.sessions.lock().unwrap().remove_session(*id);
+            user_data.rsvps.lock().unwrap().remove_rsvps_of_session(*id);
+            if let Ok(session) = session {
+                let edit = ChannelId::from(session.channel_id)
+                    .edit_message(&ctx, MessageId::from(session.message_id), |f| {
+                        f.components(|f| f)
+                    })
+                    .await;
+                if let Err(e) = edit {
+                    error!(
+                        "Failed to remove the RSVPs of session {}! {}",
+                        session.id, e
+                    );
+                }
+            }
+        }
+        if !expired.is_empty() {
+            save_table("sessions", &user_data.sessions.lock().unwrap());
+            save_table("rsvps", &user_data.rsvps.lock().unwrap());
+        }
+    }
+}
+
+async fn remind_session(
+    ctx: &SerenityContext,
+    user_data: &Data,
+    session: &Session,
+) -> Result<(), poise::serenity_prelude::SerenityError> {
+    let rsvps = user_data
+        .rsvps
+        .lock()
+        .unwrap()
+        .show_rsvps_of_session(session.id);
+    let declined: Vec<api::UserId> = rsvps
+        .iter()
+        .filter(|(_, c)| *c == RsvpChoice::Decline)
+        .map(|(u, _)| *u)
+        .collect();
+
+    let mut users: Vec<api::UserId> = user_data
+        .roles
+        .lock()
+        .unwrap()
+        .show_users_of_role(session.guild_id, session.role_id)
+        .into_iter()
+        .copied()
+        .collect();
+    users.extend(
+        rsvps
+            .iter()
+            .filter(|(_, c)| *c != RsvpChoice::Decline)
+            .map(|(u, _)| *u),
+    );
+    users.sort();
+    users.dedup();
+    let users: Vec<UserId> = users
+        .into_iter()
+        .filter(|u| !declined.contains(u))
+        .map(UserId::from)
+        .collect();
+
+    let channel_id = ChannelId::from(session.channel_id);
+    let role_name = RoleId::from(session.role_id)
+        .to_role_cached(ctx)
+        .map(|r| r.name)
+        .unwrap_or_else(|| "Game".into());
+
+    let thread = channel_id
+        .create_public_thread(ctx, MessageId::from(session.message_id), |f| {
+            f.name(format!("{} session", role_name))
+                .auto_archive_duration(1440)
+                .kind(poise::serenity_prelude::model::channel::ChannelType::PublicThread)
+        })
+        .await?;
+    THREADS_CREATED.inc();
+
+    thread
+        .send_message(ctx, |m| {
+            m.content(format!("⏰ The session starts <t:{}:R>!", session.time))
+        })
+        .await?;
+
+    let style = user_data
+        .settings
+        .lock()
+        .unwrap()
+        .settings_of_guild(session.guild_id)
+        .notification;
+    let notified = populate_thread(
+        ctx,
+        thread.id,
+        style,
+        &users,
+        &format!("the {} session", role_name),
+    )
+    .await?;
+
+    info!(
+        "({}) Reminded {} users of session {}!",
+        session.guild_id,
+        notified.len(),
+        session.id
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sessions::*;
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<FixedOffset> {
+        FixedOffset::east(3600).ymd(2022, 2, 3).and_hms(18, 0, 0)
+    }
+
+    #[test]
+    pub fn test_parse_session_time_relative() {
+        assert_eq!(
+            parse_session_time("in 2h", now()),
+            Some(Utc.ymd(2022, 2, 3).and_hms(19, 0, 0))
+        );
+        assert_eq!(
+            parse_session_time("1h 30m", now()),
+            Some(Utc.ymd(2022, 2, 3).and_hms(18, 30, 0))
+        );
+        assert_eq!(parse_session_time("in h", now()), None);
+        assert_eq!(parse_session_time("in 99999999999999999h", now()), None);
+        assert_eq!(parse_session_time("in 9223372036854775807m", now()), None);
+        assert_eq!(parse_session_time("in 9000000h", now()), None);
+        assert_eq!(
+            parse_session_time("in 8760h", now()),
+            Some(Utc.ymd(2023, 2, 3).and_hms(17, 0, 0))
+        );
+    }
+
+    #[test]
+    pub fn test_parse_session_time_absolute() {
+        assert_eq!(
+            parse_session_time("20:30", now()),
+            Some(Utc.ymd(2022, 2, 3).and_hms(19, 30, 0))
+        );
+        assert_eq!(
+            parse_session_time("08:00", now()),
+            Some(Utc.ymd(2022, 2, 4).and_hms(7, 0, 0))
+        );
+        assert_eq!(
+            parse_session_time("2022-02-10 12:00", now()),
+            Some(Utc.ymd(2022, 2, 10).and_hms(11, 0, 0))
+        );
+        assert_eq!(parse_session_time("tomorrow", now()), None);
+        assert_eq!(parse_session_time("2030-01-01 12:00", now()), None);
+    }
+}
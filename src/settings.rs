@@ -0,0 +1,207 @@
+use chrono::FixedOffset;
+use log::info;
+use poise::serenity_prelude::Color;
+use serde_derive::{Deserialize, Serialize};
+use tqdb::{remove, search};
+
+use crate::api::{GuildId, Table};
+use crate::template::{self, DEFAULT_THREAD_NAME};
+use crate::util::*;
+use crate::{Context, Error};
+
+/// How subscribers are told about a new thread.
+#[derive(
+    Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone, Copy, poise::SlashChoiceParameter,
+)]
+pub enum NotificationStyle {
+    #[name = "Mention every subscriber"]
+    Mentions,
+    #[name = "Post a summary without pinging"]
+    Summary,
+}
+
+impl Default for NotificationStyle {
+    fn default() -> Self {
+        NotificationStyle::Mentions
+    }
+}
+
+/// Per guild configuration, guilds without a record use the defaults.
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct GuildSettings {
+    pub guild_id: GuildId,
+    pub thread_name: Option<String>,
+    /// Seconds east of UTC
+    pub utc_offset: i32,
+    pub notification: NotificationStyle,
+    /// Prefix of messages that ping roles by name or alias, like `!ping`
+    pub trigger: Option<String>,
+    /// Highest position a game role may have
+    pub max_role_position: Option<i64>,
+}
+
+impl GuildSettings {
+    pub fn thread_name(&self) -> &str {
+        self.thread_name.as_deref().unwrap_or(DEFAULT_THREAD_NAME)
+    }
+
+    pub fn timezone(&self) -> FixedOffset {
+        FixedOffset::east_opt(self.utc_offset).unwrap_or_else(|| FixedOffset::east(0))
+    }
+}
+
+impl Table<GuildSettings> {
+    pub fn settings_of_guild<G: Into<GuildId>>(&self, guild_id: G) -> GuildSettings {
+        let guild_id = guild_id.into();
+        search!(&self.0 => move |it: &GuildSettings| it.guild_id == guild_id)
+            .next()
+            .cloned()
+            .unwrap_or(GuildSettings {
+                guild_id,
+                ..Default::default()
+            })
+    }
+
+    pub fn set_settings(&mut self, settings: GuildSettings) {
+        let guild_id = settings.guild_id;
+        remove!(&mut self.0 => move |it: &GuildSettings| it.guild_id == guild_id).for_each(drop);
+        let _ = self.0.insert_unique(settings);
+    }
+}
+
+/// Show or change the settings of this guild
+#[poise::command(
+    slash_command,
+    category = "game",
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn settings(
+    ctx: Context<'_>,
+    #[description = "Thread name, using {date} {time} {roles} {author} {channel} {excerpt}, or \"default\""]
+    thread_name: Option<String>,
+    #[description = "Timezone as an offset from UTC, like +2 or -05:30"] timezone: Option<String>,
+    #[description = "How subscribers are notified in new threads"] notification: Option<
+        NotificationStyle,
+    >,
+    #[description = "Prefix that pings roles by name or alias, like !ping, or \"off\""]
+    trigger: Option<String>,
+    #[description = "Highest position a game role may have, or 0 for no limit"]
+    max_position: Option<u32>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    let mut settings = ctx
+        .data()
+        .settings
+        .lock()
+        .unwrap()
+        .settings_of_guild(guild_id);
+    let changed = thread_name.is_some()
+        || timezone.is_some()
+        || notification.is_some()
+        || trigger.is_some()
+        || max_position.is_some();
+
+    if let Some(name) = thread_name {
+        if name == "default" {
+            settings.thread_name = None;
+        } else if let Err(e) = template::validate(&name) {
+            ctx.send(|f| {
+                f.embed(unsuccessful_interaction(|f| {
+                    f.description(format!("Invalid thread name! {}", e))
+                }))
+            })
+            .await?;
+            return Ok(());
+        } else {
+            settings.thread_name = Some(name);
+        }
+    }
+
+    if let Some(timezone) = timezone {
+        match template::parse_utc_offset(&timezone) {
+            Some(offset) => settings.utc_offset = offset.local_minus_utc(),
+            None => {
+                ctx.send(|f| {
+                    f.embed(unsuccessful_interaction(|f| {
+                        f.description(format!("{} is not an offset from UTC!", timezone))
+                    }))
+                })
+                .await?;
+                return Ok(());
+            }
+        }
+    }
+
+    if let Some(notification) = notification {
+        settings.notification = notification;
+    }
+
+    if let Some(trigger) = trigger {
+        settings.trigger = match trigger.trim() {
+            "off" | "" => None,
+            trigger => Some(trigger.into()),
+        };
+    }
+
+    if let Some(max_position) = max_position {
+        settings.max_role_position = match max_position {
+            0 => None,
+            max => Some(max as i64),
+        };
+    }
+
+    if changed {
+        ctx.data()
+            .settings
+            .lock()
+            .unwrap()
+            .set_settings(settings.clone());
+        info!("({}) {} changed the settings!", guild_id, ctx.author().id);
+        save_table("settings", &ctx.data().settings.lock().unwrap());
+    }
+
+    ctx.send(|f| {
+        f.embed(|f| {
+            f.title("Settings")
+                .color(Color::DARK_GREEN)
+                .field(
+                    "Thread name",
+                    format!("`{}`", settings.thread_name()),
+                    false,
+                )
+                .field("Timezone", format!("UTC{}", settings.timezone()), false)
+                .field(
+                    "Notification",
+                    match settings.notification {
+                        NotificationStyle::Mentions => "Mention every subscriber",
+                        NotificationStyle::Summary => "Post a summary without pinging",
+                    },
+                    false,
+                )
+                .field(
+                    "Trigger",
+                    match &settings.trigger {
+                        Some(trigger) => format!("`{} <role name or alias>`", trigger),
+                        None => "Off".into(),
+                    },
+                    false,
+                )
+                .field(
+                    "Highest game role position",
+                    match settings.max_role_position {
+                        Some(max) => max.to_string(),
+                        None => "No limit".into(),
+                    },
+                    false,
+                )
+        })
+    })
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,376 @@
+use chrono::Utc;
+use poise::serenity_prelude::{Color, MessageBuilder, Role, RoleId, UserId};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::Duration;
+use tqdb::{remove, search};
+
+use crate::api::{self, GuildId, Table};
+use crate::util::save_table;
+use crate::{Context, Data, Error};
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// How long events are kept for statistics.
+const RETENTION: i64 = 365 * DAY;
+
+/// How often the statistics are saved.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone, Copy)]
+pub enum RoleEventKind {
+    Join,
+    Leave,
+    Ping,
+}
+
+/// Something that happened to a role, kept for statistics.
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+pub struct RoleEvent {
+    guild_id: GuildId,
+    role_id: api::RoleId,
+    user_id: api::UserId,
+    kind: RoleEventKind,
+    /// Unix timestamp of the event
+    time: i64,
+    /// Tells apart events of the same user and kind within a second
+    #[serde(default)]
+    seq: u32,
+}
+
+impl Table<RoleEvent> {
+    pub fn add_event<G: Into<GuildId>, R: Into<api::RoleId>, U: Into<api::UserId>>(
+        &mut self,
+        guild_id: G,
+        role_id: R,
+        user_id: U,
+        kind: RoleEventKind,
+        time: i64,
+    ) {
+        let mut event = RoleEvent {
+            guild_id: guild_id.into(),
+            role_id: role_id.into(),
+            user_id: user_id.into(),
+            kind,
+            time,
+            seq: 0,
+        };
+        let same = event.clone();
+        event.seq = search!(&self.0 => move |it: &RoleEvent| it.guild_id == same.guild_id && it.role_id == same.role_id && it.user_id == same.user_id && it.kind == same.kind && it.time == same.time)
+            .count() as u32;
+        let _ = self.0.insert_unique(event);
+    }
+
+    /// Forget events from before a time, returning how many were removed.
+    pub fn prune_events(&mut self, before: i64) -> usize {
+        remove!(&mut self.0 => move |it: &RoleEvent| it.time < before).count()
+    }
+
+    pub fn show_events_of_role<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &self,
+        guild_id: G,
+        role_id: R,
+    ) -> Vec<&RoleEvent> {
+        let guild_id = guild_id.into();
+        let role_id = role_id.into();
+        search!(&self.0 => move |it: &RoleEvent| it.guild_id == guild_id && it.role_id == role_id)
+            .collect()
+    }
+
+    /// When each role of a guild last had someone join, leave or ping it.
+    pub fn last_activity_of_roles<G: Into<GuildId>>(
+        &self,
+        guild_id: G,
+    ) -> HashMap<api::RoleId, i64> {
+        let guild_id = guild_id.into();
+        latest(
+            search!(&self.0 => move |it: &RoleEvent| it.guild_id == guild_id)
+                .map(|it| (it.role_id, it.time)),
+        )
+    }
+
+    /// When each member of a role last joined, left or pinged it.
+    pub fn last_activity_of_users<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &self,
+        guild_id: G,
+        role_id: R,
+    ) -> HashMap<api::UserId, i64> {
+        latest(
+            self.show_events_of_role(guild_id, role_id)
+                .into_iter()
+                .map(|it| (it.user_id, it.time)),
+        )
+    }
+}
+
+/// The latest time of each key.
+fn latest<K: Eq + Hash>(times: impl Iterator<Item = (K, i64)>) -> HashMap<K, i64> {
+    let mut latest = HashMap::new();
+    for (key, time) in times {
+        let it = latest.entry(key).or_insert(time);
+        *it = time.max(*it);
+    }
+    latest
+}
+
+/// Record something that happened to a role, saved by [`run_stats_saves`].
+///
+/// Events older than a year are forgotten.
+pub fn record<G: Into<GuildId>, R: Into<api::RoleId>, U: Into<api::UserId>>(
+    user_data: &Data,
+    guild_id: G,
+    role_id: R,
+    user_id: U,
+    kind: RoleEventKind,
+) {
+    let now = Utc::now().timestamp();
+    let mut stats = user_data.stats.lock().unwrap();
+    stats.prune_events(now - RETENTION);
+    stats.add_event(guild_id, role_id, user_id, kind, now);
+}
+
+/// Save the statistics every few minutes, so pings don't write to disk.
+pub async fn run_stats_saves(user_data: Data) {
+    let mut interval = tokio::time::interval(SAVE_INTERVAL);
+    loop {
+        interval.tick().await;
+        save_table("stats", &user_data.stats.lock().unwrap());
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Default)]
+pub struct RoleStats {
+    pub growth_week: i64,
+    pub growth_month: i64,
+    pub pings: usize,
+    pub last_ping: Option<i64>,
+    /// Users that pinged the most, with how often they did
+    pub top_pingers: Vec<(api::UserId, usize)>,
+}
+
+/// Summarize the events of a single role.
+pub fn summarize(events: &[&RoleEvent], now: i64) -> RoleStats {
+    let growth = |days: i64| {
+        events
+            .iter()
+            .filter(|it| now - it.time <= days * DAY)
+            .map(|it| match it.kind {
+                RoleEventKind::Join => 1,
+                RoleEventKind::Leave => -1,
+                RoleEventKind::Ping => 0,
+            })
+            .sum()
+    };
+
+    let pings: Vec<&&RoleEvent> = events
+        .iter()
+        .filter(|it| it.kind == RoleEventKind::Ping)
+        .collect();
+
+    let mut pingers: Vec<(api::UserId, usize)> = Vec::new();
+    for ping in pings.iter() {
+        match pingers.iter_mut().find(|(u, _)| *u == ping.user_id) {
+            Some((_, count)) => *count += 1,
+            None => pingers.push((ping.user_id, 1)),
+        }
+    }
+    pingers.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    pingers.truncate(3);
+
+    RoleStats {
+        growth_week: growth(7),
+        growth_month: growth(30),
+        pings: pings.len(),
+        last_ping: pings.iter().map(|it| it.time).max(),
+        top_pingers: pingers,
+    }
+}
+
+fn format_last_ping(last_ping: Option<i64>) -> String {
+    match last_ping {
+        Some(time) => format!("<t:{}:R>", time),
+        None => "Never".into(),
+    }
+}
+
+/// Show statistics about the roles of this guild
+#[poise::command(slash_command, category = "game")]
+pub async fn stats(
+    ctx: Context<'_>,
+    #[description = "Selected role"] role: Option<Role>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let now = Utc::now().timestamp();
+
+    let subscribers = |role_id: api::RoleId| {
+        ctx.data()
+            .roles
+            .lock()
+            .unwrap()
+            .show_users_of_role(guild_id, role_id)
+            .len()
+    };
+    let stats_of = |role_id: api::RoleId| {
+        summarize(
+            &ctx.data()
+                .stats
+                .lock()
+                .unwrap()
+                .show_events_of_role(guild_id, role_id),
+            now,
+        )
+    };
+
+    if let Some(role) = role {
+        let stats = stats_of(role.id.0);
+        let pingers = stats
+            .top_pingers
+            .iter()
+            .fold(&mut MessageBuilder::new(), |mb, (u, count)| {
+                mb.user(UserId::from(*u))
+                    .push_line(format!(" ({} pings)", count))
+            })
+            .build();
+
+        ctx.send(|f| {
+            f.embed(|f| {
+                f.title(format!("Statistics of {}", role.name))
+                    .color(Color::DARK_GREEN)
+                    .field("Subscribers", subscribers(role.id.0), true)
+                    .field("This week", format!("{:+}", stats.growth_week), true)
+                    .field("This month", format!("{:+}", stats.growth_month), true)
+                    .field("Pings", stats.pings, true)
+                    .field("Last ping", format_last_ping(stats.last_ping), true)
+                    .field(
+                        "Most active pingers",
+                        if pingers.is_empty() {
+                            "-".into()
+                        } else {
+                            pingers
+                        },
+                        false,
+                    )
+            })
+        })
+        .await?;
+        return Ok(());
+    }
+
+    let role_ids: Vec<api::RoleId> = ctx
+        .data()
+        .roles
+        .lock()
+        .unwrap()
+        .show_roles_of_guild(guild_id)
+        .into_iter()
+        .copied()
+        .collect();
+
+    let mut rows: Vec<(api::RoleId, usize, RoleStats)> = role_ids
+        .into_iter()
+        .map(|id| (id, subscribers(id), stats_of(id)))
+        .collect();
+    rows.sort_by(|a, b| b.2.pings.cmp(&a.2.pings).then(b.1.cmp(&a.1)));
+
+    let mut message = MessageBuilder::new();
+    for (id, subscribers, stats) in rows.iter().take(20) {
+        message.role(RoleId::from(*id)).push_line(format!(
+            ": {} subscribers ({:+} this month), {} pings, last {}",
+            subscribers,
+            stats.growth_month,
+            stats.pings,
+            format_last_ping(stats.last_ping)
+        ));
+    }
+    let message = message.build();
+
+    ctx.send(|f| {
+        f.embed(|f| {
+            f.title(format!("Statistics of {}", ctx.guild().unwrap().name))
+                .color(Color::DARK_GREEN)
+                .description(if message.is_empty() {
+                    "No roles yet!".into()
+                } else {
+                    message
+                })
+        })
+    })
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::*;
+    use tqdb::Database;
+
+    fn event(user_id: api::UserId, kind: RoleEventKind, time: i64) -> RoleEvent {
+        RoleEvent {
+            guild_id: 1,
+            role_id: 1,
+            user_id,
+            kind,
+            time,
+            seq: 0,
+        }
+    }
+
+    #[test]
+    pub fn test_summarize() {
+        let now = 100 * DAY;
+        let events = vec![
+            event(1, RoleEventKind::Join, now - 40 * DAY),
+            event(2, RoleEventKind::Join, now - 20 * DAY),
+            event(3, RoleEventKind::Join, now - DAY),
+            event(2, RoleEventKind::Leave, now - DAY),
+            event(4, RoleEventKind::Join, now - DAY),
+            event(1, RoleEventKind::Ping, now - 3 * DAY),
+            event(3, RoleEventKind::Ping, now - 2 * DAY),
+            event(3, RoleEventKind::Ping, now - DAY),
+        ];
+        let events: Vec<&RoleEvent> = events.iter().collect();
+
+        assert_eq!(
+            summarize(&events, now),
+            RoleStats {
+                growth_week: 1,
+                growth_month: 2,
+                pings: 3,
+                last_ping: Some(now - DAY),
+                top_pingers: vec![(3, 2), (1, 1)],
+            }
+        );
+        assert_eq!(summarize(&[], now), RoleStats::default());
+    }
+
+    #[test]
+    pub fn test_last_activity() {
+        let table = Table(Database::from(vec![
+            event(1, RoleEventKind::Join, 10),
+            event(2, RoleEventKind::Ping, 20),
+        ]));
+        let roles = table.last_activity_of_roles(1u64);
+        assert_eq!(roles.get(&1), Some(&20));
+        assert_eq!(roles.get(&2), None);
+        let users = table.last_activity_of_users(1u64, 1u64);
+        assert_eq!(users.get(&1), Some(&10));
+        assert_eq!(users.get(&2), Some(&20));
+    }
+
+    #[test]
+    pub fn test_add_event() {
+        let mut table = Table(Database::from(vec![event(1, RoleEventKind::Ping, 10)]));
+        table.add_event(1u64, 1u64, 1u64, RoleEventKind::Ping, 10);
+        table.add_event(1u64, 1u64, 1u64, RoleEventKind::Ping, 10);
+        assert_eq!(table.show_events_of_role(1u64, 1u64).len(), 3);
+
+        table.add_event(1u64, 1u64, 2u64, RoleEventKind::Join, 20);
+        assert_eq!(table.prune_events(15), 3);
+        assert_eq!(table.last_activity_of_roles(1u64).get(&1), Some(&20));
+    }
+}
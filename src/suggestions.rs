@@ -0,0 +1,277 @@
+use log::{error, info};
+use poise::serenity_prelude::{
+    ActivityType, ButtonStyle, Color, Context as SerenityContext, Interaction,
+    InteractionResponseType, Presence, ReactionType, RoleId,
+};
+use serde_derive::{Deserialize, Serialize};
+use tqdb::{remove, search};
+
+use crate::aliases::{match_names, names_of_guild};
+use crate::api::{self, ApiError, GuildId, Table};
+use crate::components::ComponentAction;
+use crate::managed::check_game_role_id;
+use crate::stats::{record, RoleEventKind};
+use crate::util::*;
+use crate::{Context, Data, Error};
+
+/// A role that a user was already told about, so they are only told once.
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+pub struct Suggestion {
+    user_id: api::UserId,
+    guild_id: GuildId,
+    role_id: api::RoleId,
+}
+
+/// A user that never wants to be told about roles.
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+pub struct SuggestionOptOut {
+    user_id: api::UserId,
+}
+
+impl Table<Suggestion> {
+    pub fn add_suggestion<U: Into<api::UserId>, G: Into<GuildId>, R: Into<api::RoleId>>(
+        &mut self,
+        user_id: U,
+        guild_id: G,
+        role_id: R,
+    ) -> Result<(), ApiError> {
+        self.0
+            .insert_unique(Suggestion {
+                user_id: user_id.into(),
+                guild_id: guild_id.into(),
+                role_id: role_id.into(),
+            })
+            .map_err(|_| ApiError::Insertion)
+    }
+}
+
+impl Table<SuggestionOptOut> {
+    pub fn is_opted_out<U: Into<api::UserId>>(&self, user_id: U) -> bool {
+        let user_id = user_id.into();
+        search!(&self.0 => move |it: &SuggestionOptOut| it.user_id == user_id)
+            .next()
+            .is_some()
+    }
+
+    pub fn set_opted_out<U: Into<api::UserId>>(&mut self, user_id: U, opted_out: bool) {
+        let user_id = user_id.into();
+        remove!(&mut self.0 => move |it: &SuggestionOptOut| it.user_id == user_id).for_each(drop);
+        if opted_out {
+            let _ = self.0.insert_unique(SuggestionOptOut { user_id });
+        }
+    }
+}
+
+/// What a suggestion button does.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum SuggestionAction {
+    Join(GuildId, api::RoleId),
+    OptOut,
+}
+
+pub async fn on_presence_update(
+    ctx: &SerenityContext,
+    user_data: &Data,
+    presence: &Presence,
+) -> Result<(), poise::serenity_prelude::SerenityError> {
+    let guild_id = match presence.guild_id {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    let user_id = presence.user.id;
+
+    if presence.user.bot == Some(true)
+        || user_data
+            .suggestion_opt_outs
+            .lock()
+            .unwrap()
+            .is_opted_out(user_id.0)
+    {
+        return Ok(());
+    }
+
+    let games: Vec<&str> = presence
+        .activities
+        .iter()
+        .filter(|it| it.kind == ActivityType::Playing)
+        .map(|it| it.name.as_str())
+        .collect();
+
+    if games.is_empty() {
+        return Ok(());
+    }
+
+    let names = names_of_guild(ctx, user_data, guild_id.0);
+    let subscribed: Vec<api::RoleId> = user_data
+        .roles
+        .lock()
+        .unwrap()
+        .show_roles_of_user(guild_id.0, user_id.0)
+        .into_iter()
+        .copied()
+        .collect();
+
+    let mut roles: Vec<api::RoleId> = games
+        .into_iter()
+        .flat_map(|game| match_names(game, &names))
+        .filter(|id| !subscribed.contains(id))
+        .collect();
+    roles.sort();
+    roles.dedup();
+
+    for role_id in roles {
+        if user_data
+            .suggestions
+            .lock()
+            .unwrap()
+            .add_suggestion(user_id.0, guild_id.0, role_id)
+            .is_err()
+        {
+            continue;
+        }
+        save_table("suggestions", &user_data.suggestions.lock().unwrap());
+
+        let role = match RoleId::from(role_id).to_role_cached(ctx) {
+            Some(role) => role,
+            None => continue,
+        };
+        let guild_name = guild_id
+            .name(ctx)
+            .await
+            .unwrap_or_else(|| "a server".into());
+
+        // users with closed DMs shouldn't stop the other suggestions
+        let dm = match user_id.create_dm_channel(ctx).await {
+            Ok(dm) => dm,
+            Err(e) => {
+                error!("Failed to suggest {} to {}! {}", role_id, user_id, e);
+                continue;
+            }
+        };
+        let sent = dm
+            .send_message(ctx, |m| {
+                m.embed(|f| {
+                    f.title("🎮 Looks like you're playing!")
+                        .color(Color::DARK_GREEN)
+                        .description(format!(
+                            "People in {} get notified about {} with the {} role. Want to join them?",
+                            guild_name, role.name, role.name
+                        ))
+                })
+                .components(|f| {
+                    f.create_action_row(|f| {
+                        f.create_button(|f| {
+                            f.custom_id(
+                                ComponentAction::Suggestion(SuggestionAction::Join(
+                                    guild_id.0, role_id,
+                                ))
+                                .encode(),
+                            )
+                            .emoji(ReactionType::from('🔔'))
+                            .style(ButtonStyle::Primary)
+                            .label("Join this role!")
+                        })
+                        .create_button(|f| {
+                            let action = ComponentAction::Suggestion(SuggestionAction::OptOut);
+                            f.custom_id(action.encode())
+                                .style(ButtonStyle::Secondary)
+                                .label("Stop suggesting roles")
+                        })
+                    })
+                })
+            })
+            .await;
+        if let Err(e) = sent {
+            error!("Failed to suggest {} to {}! {}", role_id, user_id, e);
+            continue;
+        }
+
+        info!("({}) Suggested {} to {}!", guild_id, role_id, user_id);
+    }
+
+    Ok(())
+}
+
+pub async fn interaction_suggestion(
+    ctx: &SerenityContext,
+    user_data: &Data,
+    interaction: &Interaction,
+    action: SuggestionAction,
+) -> Result<(), poise::serenity_prelude::SerenityError> {
+    let m = match interaction.clone().message_component() {
+        Some(m) => m,
+        None => return Ok(()),
+    };
+
+    let response = match action {
+        SuggestionAction::Join(_, role_id)
+            if check_game_role_id(ctx, user_data, role_id).is_err() =>
+        {
+            "❌ Failed to add you to the role. *It isn't a game role anymore.*"
+        }
+        SuggestionAction::Join(guild_id, role_id) => {
+            let choice =
+                user_data
+                    .roles
+                    .lock()
+                    .unwrap()
+                    .add_user_to_role(guild_id, role_id, m.user.id.0);
+            match choice {
+                Ok(_) => {
+                    info!("({}) {} joined {}!", guild_id, m.user.id, role_id);
+                    record(user_data, guild_id, role_id, m.user.id, RoleEventKind::Join);
+                    save_roles(user_data);
+                    "✅ Added you to the role!"
+                }
+                Err(_) => "❌ Failed to add you to the role. *Are you already in it?*",
+            }
+        }
+        SuggestionAction::OptOut => {
+            user_data
+                .suggestion_opt_outs
+                .lock()
+                .unwrap()
+                .set_opted_out(m.user.id.0, true);
+            info!("{} opted out of suggestions!", m.user.id);
+            save_table(
+                "suggestion_opt_outs",
+                &user_data.suggestion_opt_outs.lock().unwrap(),
+            );
+            "✅ I won't suggest roles to you anymore! Use `/game suggestions` to change your mind."
+        }
+    };
+
+    m.create_interaction_response(ctx, |f| {
+        f.kind(InteractionResponseType::ChannelMessageWithSource)
+            .interaction_response_data(|f| f.content(response))
+    })
+    .await?;
+    Ok(())
+}
+
+/// Turn suggestions to join roles for games you play on or off
+#[poise::command(slash_command, category = "game", ephemeral = true)]
+pub async fn suggestions(
+    ctx: Context<'_>,
+    #[description = "Whether to suggest roles for games you play"] enabled: bool,
+) -> Result<(), Error> {
+    ctx.data()
+        .suggestion_opt_outs
+        .lock()
+        .unwrap()
+        .set_opted_out(ctx.author().id.0, !enabled);
+    save_table(
+        "suggestion_opt_outs",
+        &ctx.data().suggestion_opt_outs.lock().unwrap(),
+    );
+
+    let message = if enabled {
+        "I will suggest roles for games you play!"
+    } else {
+        "I won't suggest roles to you anymore!"
+    };
+    ctx.send(|f| f.embed(successful_interaction(|f| f.description(message))))
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use chrono::{DateTime, FixedOffset};
+use thiserror::Error;
+
+/// Discord refuses thread names longer than this many characters.
+pub const THREAD_NAME_LIMIT: usize = 100;
+
+/// The thread name used when a guild has not configured its own.
+pub const DEFAULT_THREAD_NAME: &str = "[{date}] {roles} Discussion";
+
+const EXCERPT_LENGTH: usize = 32;
+
+#[derive(Error, Debug, Eq, PartialEq)]
+pub enum TemplateError {
+    #[error("Unknown placeholder {{{0}}}")]
+    UnknownPlaceholder(String),
+    #[error("A placeholder was never closed")]
+    Unclosed,
+    #[error("The template is longer than {} characters", THREAD_NAME_LIMIT)]
+    TooLong,
+    #[error("The template could render an empty name, add {{date}}, {{time}} or some text")]
+    Empty,
+}
+
+/// Everything a thread name template can refer to.
+pub struct ThreadNameContext<'a> {
+    pub now: DateTime<FixedOffset>,
+    pub roles: &'a [String],
+    pub author: &'a str,
+    pub channel: &'a str,
+    pub content: &'a str,
+}
+
+/// Replace every `{placeholder}` in the template, failing on unknown placeholders.
+fn substitute(
+    template: &str,
+    mut value_of: impl FnMut(&str) -> Option<String>,
+) -> Result<String, TemplateError> {
+    let mut res = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        res += &rest[..start];
+        let end = rest[start..].find('}').ok_or(TemplateError::Unclosed)? + start;
+        let name = &rest[start + 1..end];
+        res += &value_of(name).ok_or_else(|| TemplateError::UnknownPlaceholder(name.into()))?;
+        rest = &rest[end + 1..];
+    }
+    res += rest;
+    Ok(res)
+}
+
+/// Placeholders that always render to some text.
+const NEVER_EMPTY: [&str; 3] = ["date", "time", "author"];
+
+/// Check that a template only uses known placeholders, leaves room for them and can't render empty.
+pub fn validate(template: &str) -> Result<(), TemplateError> {
+    let mut never_empty = false;
+    let skeleton = substitute(template, |name| match name {
+        "date" | "time" | "roles" | "author" | "channel" | "excerpt" => {
+            never_empty |= NEVER_EMPTY.contains(&name);
+            Some(String::new())
+        }
+        _ => None,
+    })?;
+    if skeleton.chars().count() >= THREAD_NAME_LIMIT {
+        return Err(TemplateError::TooLong);
+    }
+    if skeleton.trim().is_empty() && !never_empty {
+        return Err(TemplateError::Empty);
+    }
+    Ok(())
+}
+
+/// The start of a message, without mentions or line breaks.
+pub fn excerpt(content: &str) -> String {
+    let words: Vec<&str> = content
+        .split_whitespace()
+        .filter(|w| !(w.starts_with('<') && w.ends_with('>')))
+        .collect();
+    let text = words.join(" ");
+    if text.chars().count() <= EXCERPT_LENGTH {
+        return text;
+    }
+    text.chars().take(EXCERPT_LENGTH - 1).collect::<String>() + "…"
+}
+
+/// Render a thread name, dropping roles from the list until it fits within Discord's limit.
+///
+/// Falls back to [`DEFAULT_THREAD_NAME`] when the name would be blank.
+pub fn render_thread_name(
+    template: &str,
+    ctx: &ThreadNameContext<'_>,
+) -> Result<String, TemplateError> {
+    let excerpt = excerpt(ctx.content);
+    let render = |roles: String| {
+        substitute(template, |name| match name {
+            "date" => Some(ctx.now.format("%v").to_string()),
+            "time" => Some(ctx.now.format("%H:%M").to_string()),
+            "roles" => Some(roles.clone()),
+            "author" => Some(ctx.author.into()),
+            "channel" => Some(ctx.channel.into()),
+            "excerpt" => Some(excerpt.clone()),
+            _ => None,
+        })
+    };
+
+    let mut name = render(ctx.roles.join(", "))?;
+    for shown in (1..ctx.roles.len()).rev() {
+        if name.chars().count() <= THREAD_NAME_LIMIT {
+            break;
+        }
+        name = render(format!(
+            "{} +{} more",
+            ctx.roles[..shown].join(", "),
+            ctx.roles.len() - shown
+        ))?;
+    }
+    let name: String = name.chars().take(THREAD_NAME_LIMIT).collect();
+    if name.trim().is_empty() && template != DEFAULT_THREAD_NAME {
+        return render_thread_name(DEFAULT_THREAD_NAME, ctx);
+    }
+    Ok(name.trim().into())
+}
+
+/// Parse a timezone written as an offset from UTC, like `+2`, `-05:30` or `UTC+10`.
+pub fn parse_utc_offset(text: &str) -> Option<FixedOffset> {
+    let text = text.trim();
+    let text = text
+        .strip_prefix("UTC")
+        .or_else(|| text.strip_prefix("utc"))
+        .unwrap_or(text);
+    if text.is_empty() {
+        return FixedOffset::east_opt(0);
+    }
+    let (sign, text) = match text.get(..1) {
+        Some("+") => (1, &text[1..]),
+        Some("-") => (-1, &text[1..]),
+        _ => (1, text),
+    };
+    // `parse` would accept another sign, like in `+-3`
+    if !text.chars().all(|c| c.is_ascii_digit() || c == ':') {
+        return None;
+    }
+    let (hours, minutes) = match text.split_once(':') {
+        Some((h, m)) => (h.parse::<i32>().ok()?, m.parse::<i32>().ok()?),
+        None => (text.parse::<i32>().ok()?, 0),
+    };
+    if minutes >= 60 || hours > 24 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::template::*;
+    use chrono::TimeZone;
+
+    fn test_context(roles: &[String]) -> ThreadNameContext<'_> {
+        ThreadNameContext {
+            now: FixedOffset::east(0).ymd(2022, 2, 3).and_hms(16, 5, 0),
+            roles,
+            author: "kace",
+            channel: "lfg",
+            content: "<@&1234> anyone up for a game tonight? bring snacks",
+        }
+    }
+
+    #[test]
+    pub fn test_validate() {
+        assert!(validate(DEFAULT_THREAD_NAME).is_ok());
+        assert!(validate("{author} in {channel} at {time}: {excerpt}").is_ok());
+        assert_eq!(
+            validate("{nope}"),
+            Err(TemplateError::UnknownPlaceholder("nope".into()))
+        );
+        assert_eq!(validate("{roles"), Err(TemplateError::Unclosed));
+        assert_eq!(validate(&"a".repeat(100)), Err(TemplateError::TooLong));
+        assert_eq!(validate(" {excerpt} "), Err(TemplateError::Empty));
+        assert_eq!(validate("{roles}{channel}"), Err(TemplateError::Empty));
+        assert!(validate("{author}").is_ok());
+    }
+
+    #[test]
+    pub fn test_render_thread_name() {
+        let roles = vec!["Apex".to_string(), "Valorant".to_string()];
+        let ctx = test_context(&roles);
+        assert_eq!(
+            render_thread_name(DEFAULT_THREAD_NAME, &ctx).unwrap(),
+            "[ 3-Feb-2022] Apex, Valorant Discussion"
+        );
+        assert_eq!(
+            render_thread_name("{author} in #{channel} at {time}: {excerpt}", &ctx).unwrap(),
+            "kace in #lfg at 16:05: anyone up for a game tonight? b…"
+        );
+        let empty = ThreadNameContext {
+            content: "<@&1234>",
+            ..test_context(&roles)
+        };
+        assert_eq!(
+            render_thread_name(" {excerpt} ", &empty).unwrap(),
+            "[ 3-Feb-2022] Apex, Valorant Discussion"
+        );
+    }
+
+    #[test]
+    pub fn test_render_thread_name_truncates_roles() {
+        let roles: Vec<String> = (0..20).map(|i| format!("Game number {}", i)).collect();
+        let ctx = test_context(&roles);
+        let name = render_thread_name("{roles} Discussion", &ctx).unwrap();
+        assert!(name.chars().count() <= THREAD_NAME_LIMIT);
+        assert!(name.starts_with("Game number 0, Game number 1"));
+        assert!(name.ends_with("more Discussion"));
+    }
+
+    #[test]
+    pub fn test_parse_utc_offset() {
+        assert_eq!(parse_utc_offset("+2"), FixedOffset::east_opt(7200));
+        assert_eq!(parse_utc_offset("UTC-05:30"), FixedOffset::west_opt(19800));
+        assert_eq!(parse_utc_offset("UTC"), FixedOffset::east_opt(0));
+        assert_eq!(parse_utc_offset("tomorrow"), None);
+        assert_eq!(parse_utc_offset("+-3"), None);
+        assert_eq!(parse_utc_offset("--3"), None);
+        assert_eq!(parse_utc_offset("2:-30"), None);
+        assert_eq!(parse_utc_offset("+99999999"), None);
+    }
+}
@@ -1,7 +1,13 @@
+use chrono::Utc;
 use dotenv as env;
 use log::{error, info};
 use poise::serenity_prelude::{Color, CreateEmbed};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::PathBuf;
 
+use crate::api::Table;
+use crate::metrics::{DB_ROWS, SAVE_DURATION};
 use crate::Data;
 
 pub fn successful_interaction(
@@ -24,14 +30,142 @@ pub fn unsuccessful_interaction(
     }
 }
 
+/// Discord refuses messages longer than this many characters.
+pub const MESSAGE_LIMIT: usize = 2000;
+
+/// Join items with spaces into as few messages as possible, without splitting an item.
+pub fn join_within_limit<S: AsRef<str>>(items: &[S], limit: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for item in items {
+        let item = item.as_ref();
+        if !current.is_empty() && current.len() + 1 + item.len() > limit {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current += item;
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Discord refuses embed field values longer than this many characters.
+pub const FIELD_LIMIT: usize = 1024;
+
+/// Join items with spaces into one text, ending with how many more there are when they don't fit.
+pub fn join_truncated<S: AsRef<str>>(items: &[S], limit: usize) -> String {
+    let mut shown = items.len();
+    loop {
+        let mut text = items[..shown]
+            .iter()
+            .map(|it| it.as_ref())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if shown < items.len() {
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            text += &format!("+{} more", items.len() - shown);
+        }
+        if text.len() <= limit || shown == 0 {
+            return text;
+        }
+        shown -= 1;
+    }
+}
+
+/// Location of a table file, inside of `BOT_DATA_DIR` or the working directory.
+pub fn table_path(name: &str) -> PathBuf {
+    let mut path: PathBuf = env::var("BOT_DATA_DIR")
+        .unwrap_or_else(|_| ".".into())
+        .into();
+    path.push(format!("{}.db", name));
+    path
+}
+
+/// Save the subscriptions, which most commands change.
+pub fn save_roles(ctx: &Data) {
+    let _timer = SAVE_DURATION.start_timer();
+    let roles = ctx.roles.lock().unwrap();
+    match roles.save(env::var("BOT_ROLES_DB").unwrap()) {
+        Err(e) => error!("Error! {}", e),
+        Ok(_) => {
+            info!("Saved to {}.", env::var("BOT_ROLES_DB").unwrap());
+            ctx.health.lock().unwrap().last_save = Some(Utc::now().timestamp());
+        }
+    }
+    DB_ROWS
+        .with_label_values(&["roles"])
+        .set(roles.row_count() as i64);
+}
+
+/// Save every table, for changes that touch many of them.
 pub fn save_to_db(ctx: &Data) {
-    match ctx
-        .0
-        .lock()
-        .unwrap()
-        .save(env::var("BOT_ROLES_DB").unwrap())
-    {
+    save_roles(ctx);
+    save_table("permissions", &ctx.permissions.lock().unwrap());
+    save_table("settings", &ctx.settings.lock().unwrap());
+    save_table("aliases", &ctx.aliases.lock().unwrap());
+    save_table("channels", &ctx.channels.lock().unwrap());
+    save_table("sessions", &ctx.sessions.lock().unwrap());
+    save_table("rsvps", &ctx.rsvps.lock().unwrap());
+    save_table("lfg", &ctx.lfg.lock().unwrap());
+    save_table("voice", &ctx.voice.lock().unwrap());
+    save_table("suggestions", &ctx.suggestions.lock().unwrap());
+    save_table(
+        "suggestion_opt_outs",
+        &ctx.suggestion_opt_outs.lock().unwrap(),
+    );
+    save_table("stats", &ctx.stats.lock().unwrap());
+    save_table("buttons", &ctx.buttons.lock().unwrap());
+    save_table("panels", &ctx.panels.lock().unwrap());
+    save_table("categories", &ctx.categories.lock().unwrap());
+    save_table("managed", &ctx.managed.lock().unwrap());
+}
+
+/// Save a single table, when nothing else changed.
+pub fn save_table<T: Serialize + DeserializeOwned + Clone + PartialEq>(
+    name: &str,
+    table: &Table<T>,
+) {
+    let _timer = SAVE_DURATION.start_timer();
+    let path = table_path(name);
+    match table.save(&path) {
         Err(e) => error!("Error! {}", e),
-        Ok(_) => info!("Saved to {}.", env::var("BOT_ROLES_DB").unwrap()),
+        Ok(_) => info!("Saved to {}.", path.display()),
+    }
+    DB_ROWS
+        .with_label_values(&[name])
+        .set(table.row_count() as i64);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::*;
+
+    #[test]
+    pub fn test_join_within_limit() {
+        let items = vec!["<@1>", "<@22>", "<@333>", "<@4444>"];
+        assert_eq!(
+            join_within_limit(&items, 12),
+            vec!["<@1> <@22>", "<@333>", "<@4444>"]
+        );
+        assert_eq!(
+            join_within_limit(&items, 100),
+            vec!["<@1> <@22> <@333> <@4444>"]
+        );
+        assert!(join_within_limit::<&str>(&[], 100).is_empty());
+    }
+
+    #[test]
+    pub fn test_join_truncated() {
+        let items = vec!["<@1>", "<@22>", "<@333>", "<@4444>"];
+        assert_eq!(join_truncated(&items, 100), "<@1> <@22> <@333> <@4444>");
+        assert_eq!(join_truncated(&items, 20), "<@1> <@22> +2 more");
+        assert_eq!(join_truncated(&items, 8), "+4 more");
+        assert_eq!(join_truncated::<&str>(&[], 100), "");
     }
 }
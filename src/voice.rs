@@ -0,0 +1,309 @@
+use chrono::Utc;
+use log::info;
+use poise::serenity_prelude::{
+    ChannelId, Context as SerenityContext, GuildChannel, MessageBuilder, Role, RoleId, UserId,
+    VoiceState,
+};
+use serde_derive::{Deserialize, Serialize};
+use tqdb::{remove, search};
+
+use crate::api::{self, GuildId, Snowflake, Table};
+use crate::events::populate_thread;
+use crate::game::ensure_game_role;
+use crate::metrics::THREADS_CREATED;
+use crate::util::*;
+use crate::{Context, Data, Error};
+
+/// A voice channel whose sessions notify the subscribers of a role.
+#[derive(Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Clone)]
+pub struct VoiceLink {
+    pub guild_id: GuildId,
+    pub voice_channel_id: Snowflake,
+    pub role_id: api::RoleId,
+    /// Channel the notification thread is created in
+    pub text_channel_id: Snowflake,
+    /// How many people have to be in the voice channel before pinging
+    pub threshold: usize,
+    /// Minutes between pings for the same voice channel
+    pub cooldown: i64,
+}
+
+impl Table<VoiceLink> {
+    pub fn set_link(&mut self, link: VoiceLink) {
+        self.remove_link(link.guild_id, link.voice_channel_id);
+        let _ = self.0.insert_unique(link);
+    }
+
+    pub fn remove_link<G: Into<GuildId>, C: Into<Snowflake>>(
+        &mut self,
+        guild_id: G,
+        voice_channel_id: C,
+    ) -> Option<VoiceLink> {
+        let guild_id = guild_id.into();
+        let voice_channel_id = voice_channel_id.into();
+        remove!(&mut self.0 => move |it: &VoiceLink| it.guild_id == guild_id && it.voice_channel_id == voice_channel_id)
+            .next()
+    }
+
+    pub fn link_of_channel<G: Into<GuildId>, C: Into<Snowflake>>(
+        &self,
+        guild_id: G,
+        voice_channel_id: C,
+    ) -> Option<&VoiceLink> {
+        let guild_id = guild_id.into();
+        let voice_channel_id = voice_channel_id.into();
+        search!(&self.0 => move |it: &VoiceLink| it.guild_id == guild_id && it.voice_channel_id == voice_channel_id)
+            .next()
+    }
+
+    /// Point the links of a role at another role after a merge.
+    pub fn move_role<G: Into<GuildId>, R: Into<api::RoleId>>(
+        &mut self,
+        guild_id: G,
+        from: R,
+        into: R,
+    ) -> usize {
+        let guild_id = guild_id.into();
+        let from = from.into();
+        let into = into.into();
+        let moved: Vec<VoiceLink> = remove!(&mut self.0 => move |it: &VoiceLink| it.guild_id == guild_id && it.role_id == from)
+            .collect();
+        for link in moved.iter() {
+            self.set_link(VoiceLink {
+                role_id: into,
+                ..link.clone()
+            });
+        }
+        moved.len()
+    }
+
+    pub fn show_links_of_guild<G: Into<GuildId>>(&self, guild_id: G) -> Vec<&VoiceLink> {
+        let guild_id = guild_id.into();
+        search!(&self.0 => move |it: &VoiceLink| it.guild_id == guild_id).collect()
+    }
+}
+
+/// Whether a voice channel with this many people should ping its role now.
+pub fn should_ping(link: &VoiceLink, present: usize, last_ping: Option<i64>, now: i64) -> bool {
+    present >= link.threshold && last_ping.map_or(true, |last| now - last >= link.cooldown * 60)
+}
+
+pub async fn on_voice_state_update(
+    ctx: &SerenityContext,
+    user_data: &Data,
+    old: &Option<VoiceState>,
+    new: &VoiceState,
+) -> Result<(), poise::serenity_prelude::SerenityError> {
+    let (guild_id, channel_id) = match (new.guild_id, new.channel_id) {
+        (Some(guild_id), Some(channel_id)) => (guild_id, channel_id),
+        _ => return Ok(()),
+    };
+
+    // only joining a channel counts, not muting or deafening
+    if old.as_ref().and_then(|it| it.channel_id) == Some(channel_id) {
+        return Ok(());
+    }
+
+    let link = match user_data
+        .voice
+        .lock()
+        .unwrap()
+        .link_of_channel(guild_id.0, channel_id.0)
+    {
+        Some(link) => link.clone(),
+        None => return Ok(()),
+    };
+
+    let present: Vec<UserId> = match guild_id.to_guild_cached(ctx).await {
+        Some(guild) => guild
+            .voice_states
+            .values()
+            .filter(|it| it.channel_id == Some(channel_id))
+            .map(|it| it.user_id)
+            .collect(),
+        None => return Ok(()),
+    };
+
+    let now = Utc::now().timestamp();
+    {
+        let mut cooldowns = user_data.voice_cooldowns.lock().unwrap();
+        if !should_ping(
+            &link,
+            present.len(),
+            cooldowns.get(&channel_id).copied(),
+            now,
+        ) {
+            return Ok(());
+        }
+        cooldowns.insert(channel_id, now);
+    }
+
+    let users: Vec<UserId> = user_data
+        .roles
+        .lock()
+        .unwrap()
+        .show_users_of_role(guild_id.0, link.role_id)
+        .into_iter()
+        .copied()
+        .map(UserId::from)
+        .filter(|u| !present.contains(u))
+        .collect();
+
+    if users.is_empty() {
+        return Ok(());
+    }
+
+    let role_name = RoleId::from(link.role_id)
+        .to_role_cached(ctx)
+        .map(|r| r.name)
+        .unwrap_or_else(|| "Game".into());
+
+    let text_channel_id = ChannelId::from(link.text_channel_id);
+    let anchor = text_channel_id
+        .send_message(ctx, |m| {
+            m.content(
+                MessageBuilder::new()
+                    .push("🔊 ")
+                    .push(present.len().to_string())
+                    .push(" people are playing ")
+                    .push_safe(&role_name)
+                    .push(" in ")
+                    .channel(channel_id)
+                    .push("!")
+                    .build(),
+            )
+        })
+        .await?;
+
+    let thread = text_channel_id
+        .create_public_thread(ctx, anchor.id, |f| {
+            f.name(format!("{} voice session", role_name))
+                .auto_archive_duration(1440)
+                .kind(poise::serenity_prelude::model::channel::ChannelType::PublicThread)
+        })
+        .await?;
+    THREADS_CREATED.inc();
+
+    let style = user_data
+        .settings
+        .lock()
+        .unwrap()
+        .settings_of_guild(guild_id.0)
+        .notification;
+    populate_thread(ctx, thread.id, style, &users, &role_name).await?;
+
+    info!(
+        "({}) Notified {} of a voice session in {}!",
+        guild_id, link.role_id, channel_id
+    );
+    Ok(())
+}
+
+/// Show or change which voice channels notify a role
+#[poise::command(
+    slash_command,
+    category = "game",
+    required_permissions = "MANAGE_CHANNELS"
+)]
+pub async fn voice(
+    ctx: Context<'_>,
+    #[description = "Selected voice channel"] channel: Option<GuildChannel>,
+    #[description = "Role to notify, leave empty to unlink"] role: Option<Role>,
+    #[description = "Channel to create the thread in (default here)"] announce_in: Option<
+        GuildChannel,
+    >,
+    #[description = "People needed in voice before notifying (default 2)"] threshold: Option<u32>,
+    #[description = "Minutes between notifications (default 60)"] cooldown: Option<u32>,
+) -> Result<(), Error> {
+    let guild_id = match ctx.guild_id() {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+
+    if let (Some(_), Some(role)) = (&channel, &role) {
+        if !ensure_game_role(&ctx, role).await? {
+            return Ok(());
+        }
+    }
+
+    if let Some(channel) = &channel {
+        match &role {
+            Some(role) => ctx.data().voice.lock().unwrap().set_link(VoiceLink {
+                guild_id: guild_id.0,
+                voice_channel_id: channel.id.0,
+                role_id: role.id.0,
+                text_channel_id: announce_in.map_or(ctx.channel_id().0, |c| c.id.0),
+                threshold: threshold.unwrap_or(2) as usize,
+                cooldown: cooldown.unwrap_or(60) as i64,
+            }),
+            None => {
+                ctx.data()
+                    .voice
+                    .lock()
+                    .unwrap()
+                    .remove_link(guild_id.0, channel.id.0);
+            }
+        }
+        info!(
+            "({}) {} changed the link of voice channel {}!",
+            guild_id,
+            ctx.author().id,
+            channel.id
+        );
+        save_table("voice", &ctx.data().voice.lock().unwrap());
+    }
+
+    let links: Vec<VoiceLink> = ctx
+        .data()
+        .voice
+        .lock()
+        .unwrap()
+        .show_links_of_guild(guild_id.0)
+        .into_iter()
+        .cloned()
+        .collect();
+
+    let mut message = MessageBuilder::new();
+    if links.is_empty() {
+        message.push("No voice channels notify roles!");
+    }
+    for link in links {
+        message
+            .channel(ChannelId::from(link.voice_channel_id))
+            .push(" notifies ")
+            .role(RoleId::from(link.role_id))
+            .push(" in ")
+            .channel(ChannelId::from(link.text_channel_id))
+            .push_line(format!(
+                " with {} people, at most every {} minutes",
+                link.threshold, link.cooldown
+            ));
+    }
+    let message = message.build();
+
+    ctx.send(|f| f.embed(successful_interaction(|f| f.description(message))))
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::voice::*;
+
+    #[test]
+    pub fn test_should_ping() {
+        let link = VoiceLink {
+            guild_id: 1,
+            voice_channel_id: 2,
+            role_id: 3,
+            text_channel_id: 4,
+            threshold: 3,
+            cooldown: 60,
+        };
+        assert!(!should_ping(&link, 2, None, 0));
+        assert!(should_ping(&link, 3, None, 0));
+        assert!(!should_ping(&link, 3, Some(0), 59 * 60));
+        assert!(should_ping(&link, 4, Some(0), 60 * 60));
+    }
+}